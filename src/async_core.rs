@@ -0,0 +1,159 @@
+use std::io;
+use std::collections::VecDeque;
+use std::task::{ready, Poll};
+
+use crate::{ConsumerError, ConsumerEvent, TerminationReason};
+
+
+// What polling a single write to the writer gives back.
+pub(crate) type PollWrite = Poll<io::Result<usize>>;
+
+// The demand and buffering shared by the async adapters. Each runtime's
+// adapter only has to supply a way of polling a single write to its writer.
+pub(crate) struct AsyncCore {
+    demand: usize,
+    event_queue: VecDeque<ConsumerEvent>,
+    buffered: Option<Vec<u8>>,
+    coalesce_requests: bool,
+    closed: bool,
+}
+
+impl AsyncCore {
+    pub(crate) fn new(initial_demand: usize) -> AsyncCore {
+        let mut core = AsyncCore {
+            demand: initial_demand,
+            event_queue: VecDeque::new(),
+            buffered: None,
+            coalesce_requests: true,
+            closed: false,
+        };
+
+        core.emit(ConsumerEvent::Request(initial_demand));
+
+        core
+    }
+
+    pub(crate) fn set_coalesce_requests(&mut self, coalesce: bool) {
+        self.coalesce_requests = coalesce;
+    }
+
+    pub(crate) fn demand(&self) -> usize {
+        self.demand
+    }
+
+    pub(crate) fn pending_bytes(&self) -> usize {
+        self.buffered.as_ref().map_or(0, Vec::len)
+    }
+
+    pub(crate) fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.event_queue.pop_front()
+    }
+
+    pub(crate) fn poll_write(&mut self, write: &mut impl FnMut(&[u8]) -> PollWrite, data: &[u8]) -> Poll<Result<(), ConsumerError>> {
+        if self.closed {
+            return Poll::Ready(Err(ConsumerError::Terminated));
+        }
+
+        ready!(self.poll_drain(write))?;
+
+        if self.demand == 0 {
+            return Poll::Ready(Err(ConsumerError::WriteWithoutRequest));
+        }
+
+        let (n, err) = write_until_pending(write, data);
+
+        if let Some(err) = err {
+            return Poll::Ready(Err(self.terminate_with(err)));
+        }
+
+        if n != data.len() {
+            self.buffered = Some(data[n..].into());
+            self.demand -= 1;
+        }
+        else {
+            self.emit(ConsumerEvent::Request(1));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    pub(crate) fn poll_drain(&mut self, write: &mut impl FnMut(&[u8]) -> PollWrite) -> Poll<Result<(), ConsumerError>> {
+        if let Some(mut buffered) = self.buffered.take() {
+            let (n, err) = write_until_pending(write, &buffered);
+
+            if let Some(err) = err {
+                return Poll::Ready(Err(self.terminate_with(err)));
+            }
+
+            if n != buffered.len() {
+                buffered.drain(..n);
+                self.buffered = Some(buffered);
+                return Poll::Pending;
+            }
+
+            self.demand += 1;
+            self.emit(ConsumerEvent::Request(1));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) {
+        if event == ConsumerEvent::Request(0) {
+            return;
+        }
+
+        if let ConsumerEvent::Request(m) = event {
+            let coalesce = self.coalesce_requests;
+
+            if let Some(ConsumerEvent::Request(n)) = self.event_queue.back_mut().filter(|_| coalesce) {
+                *n += m;
+                return;
+            }
+        }
+
+        self.event_queue.push_back(event);
+    }
+
+    fn terminate_with(&mut self, err: io::Error) -> ConsumerError {
+        let unacked = self.buffered.take().map_or(0, |_| 1);
+        self.demand = 0;
+        self.closed = true;
+        self.emit(ConsumerEvent::Termination { reason: TerminationReason::WriteError(err.kind()), unacked });
+
+        if err.kind() == io::ErrorKind::WriteZero {
+            ConsumerError::WriteZero
+        }
+        else {
+            ConsumerError::Io(err)
+        }
+    }
+}
+
+// A writer returning Ok(0) will never wake us up again, so that counts as an
+// error rather than something to wait on.
+fn write_until_pending(write: &mut impl FnMut(&[u8]) -> PollWrite, data: &[u8]) -> (usize, Option<io::Error>) {
+    let mut written = 0;
+
+    while written < data.len() {
+        match write(&data[written..]) {
+            Poll::Pending => {
+                break;
+            },
+            Poll::Ready(Ok(0)) => {
+                return (written, Some(io::ErrorKind::WriteZero.into()));
+            },
+            Poll::Ready(Ok(n)) => {
+                written += n;
+            },
+            Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => {
+                continue;
+            },
+            Poll::Ready(Err(e)) => {
+                return (written, Some(e));
+            },
+        }
+    }
+
+    (written, None)
+}
@@ -0,0 +1,77 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_std::io::Write as AsyncWrite;
+
+use crate::{ConsumerError, ConsumerEvent};
+use crate::async_core::AsyncCore;
+
+
+// The async-std counterpart to AsyncWriteAdapterConsumer, with the same
+// demand and buffering behaviour.
+pub struct AsyncStdWriteAdapterConsumer<W: AsyncWrite + Unpin> {
+    writer: W,
+    core: AsyncCore,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncStdWriteAdapterConsumer<W> {
+    pub fn new(writer: W) -> AsyncStdWriteAdapterConsumer<W> {
+        AsyncStdWriteAdapterConsumer::with_demand(writer, 1)
+    }
+
+    pub fn with_demand(writer: W, initial_demand: usize) -> AsyncStdWriteAdapterConsumer<W> {
+        AsyncStdWriteAdapterConsumer {
+            writer,
+            core: AsyncCore::new(initial_demand),
+        }
+    }
+
+    // Same as the coalesce_requests builder option on WriteAdapterConsumer,
+    // and on by default like there.
+    pub fn coalesce_requests(mut self, coalesce: bool) -> AsyncStdWriteAdapterConsumer<W> {
+        self.core.set_coalesce_requests(coalesce);
+        self
+    }
+
+    pub fn demand(&self) -> usize {
+        self.core.demand()
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.core.pending_bytes()
+    }
+
+    pub fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.core.next_event()
+    }
+
+    pub fn poll_write(&mut self, cx: &mut Context, data: &[u8]) -> Poll<Result<(), ConsumerError>> {
+        let AsyncStdWriteAdapterConsumer { writer, core } = self;
+        core.poll_write(&mut |buf: &[u8]| Pin::new(&mut *writer).poll_write(cx, buf), data)
+    }
+
+    pub fn poll_drain(&mut self, cx: &mut Context) -> Poll<Result<(), ConsumerError>> {
+        let AsyncStdWriteAdapterConsumer { writer, core } = self;
+        core.poll_drain(&mut |buf: &[u8]| Pin::new(&mut *writer).poll_write(cx, buf))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::task::Waker;
+
+    #[test]
+    fn poll_write_to_memory() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut consumer = AsyncStdWriteAdapterConsumer::new(async_std::io::Cursor::new(Vec::new()));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.poll_write(&mut cx, &[65, 66]), Poll::Ready(Ok(())));
+        assert_eq!(consumer.poll_drain(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.writer.get_ref(), &vec![65, 66]);
+    }
+}
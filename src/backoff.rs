@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+
+// Exponential backoff for a writer that keeps blocking. The delay starts at
+// base after the first blocked attempt and doubles with each one after that,
+// up to max.
+pub(crate) struct Backoff {
+    base: Duration,
+    max: Duration,
+    attempts: u32,
+}
+
+impl Backoff {
+    pub(crate) fn new(base: Duration, max: Duration) -> Backoff {
+        Backoff {
+            base,
+            max,
+            attempts: 0,
+        }
+    }
+
+    pub(crate) fn blocked(&mut self) {
+        self.attempts = self.attempts.saturating_add(1);
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.attempts = 0;
+    }
+
+    // How long to wait before trying again, or None if the last attempt
+    // wasn't blocked.
+    pub(crate) fn delay(&self) -> Option<Duration> {
+        if self.attempts == 0 {
+            return None;
+        }
+
+        let factor = 1u32.checked_shl(self.attempts - 1).unwrap_or(u32::MAX);
+        Some(self.base.checked_mul(factor).unwrap_or(self.max).min(self.max))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(25));
+        assert_eq!(backoff.delay(), None);
+
+        backoff.blocked();
+        assert_eq!(backoff.delay(), Some(Duration::from_millis(10)));
+        backoff.blocked();
+        assert_eq!(backoff.delay(), Some(Duration::from_millis(20)));
+        backoff.blocked();
+        assert_eq!(backoff.delay(), Some(Duration::from_millis(25)));
+
+        for _ in 0..100 {
+            backoff.blocked();
+        }
+        assert_eq!(backoff.delay(), Some(Duration::from_millis(25)));
+
+        backoff.reset();
+        assert_eq!(backoff.delay(), None);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{BoxedWriteAdapterConsumer, Consumer, ConsumerError};
+
+
+// Fans data out to any number of subscribers, each with its own demand.
+// Unlike TeeConsumer nobody waits for the slowest subscriber: data goes
+// straight to every subscriber that can take it, and queues up for the rest
+// until they ask for more.
+pub struct Broadcaster<'a> {
+    subscribers: BTreeMap<usize, Subscriber<'a>>,
+    next_id: usize,
+}
+
+struct Subscriber<'a> {
+    consumer: BoxedWriteAdapterConsumer<'a>,
+    // Broadcasts that arrived while the consumer had no demand.
+    backlog: VecDeque<Vec<u8>>,
+}
+
+impl<'a> Broadcaster<'a> {
+    pub fn new() -> Broadcaster<'a> {
+        Broadcaster {
+            subscribers: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    // Adds a subscriber and returns the id to refer to it by.
+    pub fn subscribe(&mut self, consumer: BoxedWriteAdapterConsumer<'a>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.insert(id, Subscriber {
+            consumer,
+            backlog: VecDeque::new(),
+        });
+
+        id
+    }
+
+    // Drops the subscriber, along with anything still queued for it, and
+    // hands back its consumer.
+    pub fn remove(&mut self, id: usize) -> Option<BoxedWriteAdapterConsumer<'a>> {
+        self.subscribers.remove(&id).map(|subscriber| subscriber.consumer)
+    }
+
+    pub fn demand(&self, id: usize) -> Option<usize> {
+        self.subscribers.get(&id).map(|subscriber| subscriber.consumer.demand())
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    // Sends data to every subscriber. Any subscriber whose consumer fails is
+    // removed, and the errors are returned along with their ids.
+    pub fn broadcast(&mut self, data: &[u8]) -> Vec<(usize, ConsumerError)> {
+        self.each(|subscriber| {
+            subscriber.backlog.push_back(data.to_vec());
+            subscriber.send_backlog()
+        })
+    }
+
+    // Updates every subscriber and sends on whatever was queued for the ones
+    // that now have demand. Failures are handled as in broadcast().
+    pub fn update(&mut self) -> Vec<(usize, ConsumerError)> {
+        self.each(|subscriber| {
+            subscriber.consumer.update();
+            subscriber.send_backlog()
+        })
+    }
+
+    fn each(&mut self, mut f: impl FnMut(&mut Subscriber<'a>) -> Result<(), ConsumerError>) -> Vec<(usize, ConsumerError)> {
+        let mut failed = Vec::new();
+
+        for (&id, subscriber) in self.subscribers.iter_mut() {
+            if let Err(err) = f(subscriber) {
+                failed.push((id, err));
+            }
+
+            // Demand is read straight from the consumer, so the events are
+            // only cleared out to stop them piling up.
+            subscriber.consumer.drain_events();
+        }
+
+        for (id, _) in failed.iter() {
+            self.subscribers.remove(id);
+        }
+
+        failed
+    }
+}
+
+impl<'a> Subscriber<'a> {
+    fn send_backlog(&mut self) -> Result<(), ConsumerError> {
+        if self.consumer.closed {
+            return Err(ConsumerError::Terminated);
+        }
+
+        while self.consumer.demand() > 0 {
+            match self.backlog.pop_front() {
+                Some(data) => {
+                    self.consumer.write(&data)?;
+                },
+                None => {
+                    break;
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Default for Broadcaster<'a> {
+    fn default() -> Broadcaster<'a> {
+        Broadcaster::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::WriteAdapterConsumer;
+    use crate::test_util::BlockingWriter;
+
+    #[test]
+    fn subscribers_keep_their_own_pace() {
+        let mut fast = Vec::new();
+        let mut slow = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut broadcaster = Broadcaster::new();
+            let fast_id = broadcaster.subscribe(WriteAdapterConsumer::new(Box::new(&mut fast)));
+            let slow_id = broadcaster.subscribe(WriteAdapterConsumer::new(Box::new(&mut slow)));
+
+            assert!(broadcaster.broadcast(&[65]).is_empty());
+            assert!(broadcaster.broadcast(&[66]).is_empty());
+            assert_eq!(broadcaster.demand(fast_id), Some(1));
+            assert_eq!(broadcaster.demand(slow_id), Some(0));
+
+            assert!(broadcaster.update().is_empty());
+            assert_eq!(broadcaster.demand(slow_id), Some(1));
+
+            assert!(broadcaster.remove(fast_id).is_some());
+            assert_eq!(broadcaster.demand(fast_id), None);
+            assert!(broadcaster.broadcast(&[67]).is_empty());
+        }
+        assert_eq!(fast, vec![65, 66]);
+        assert_eq!(slow.data, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn failed_subscriber_is_removed() {
+        let mut broadcaster = Broadcaster::new();
+        let id = broadcaster.subscribe(WriteAdapterConsumer::new(Box::new(Vec::new())));
+        broadcaster.subscribers.get_mut(&id).unwrap().consumer.cancel();
+
+        assert_eq!(broadcaster.broadcast(&[65]), vec![(id, ConsumerError::Terminated)]);
+        assert!(broadcaster.is_empty());
+    }
+}
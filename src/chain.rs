@@ -0,0 +1,141 @@
+use std::io::{self, Write};
+use std::collections::VecDeque;
+
+use crate::{Consumer, ConsumerError, ConsumerEvent, UpdateResult, WriteAdapterConsumer};
+
+
+// Routes bytes through a list of writers, each taking at most its own budget
+// before the next one takes over. The routing happens below the demand
+// handling, so buffering and a write that spans a boundary work the same as
+// with a single writer. Once every budget is used up the writer accepts
+// nothing more, which terminates the stream with WriteZero.
+pub struct ChainConsumer<'a> {
+    inner: WriteAdapterConsumer<'a, ChainWriter<'a>>,
+}
+
+struct ChainWriter<'a> {
+    writers: VecDeque<(usize, Box<dyn Write + 'a>)>,
+}
+
+impl<'a> ChainConsumer<'a> {
+    pub fn new(writers: Vec<(usize, Box<dyn Write + 'a>)>) -> ChainConsumer<'a> {
+        ChainConsumer {
+            inner: WriteAdapterConsumer::new(ChainWriter {
+                writers: writers.into(),
+            }),
+        }
+    }
+
+    pub fn demand(&self) -> usize {
+        self.inner.demand()
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+}
+
+impl<'a> Consumer<[u8]> for ChainConsumer<'a> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        self.inner.write(data)
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        self.inner.emit(event)
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.inner.next_event()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        self.inner.update()
+    }
+
+    fn request(&mut self, n: usize) {
+        self.inner.request(n);
+    }
+}
+
+impl<'a> Write for ChainWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        while let Some((0, _)) = self.writers.front() {
+            let (_, mut writer) = self.writers.pop_front().unwrap();
+            writer.flush()?;
+        }
+
+        match self.writers.front_mut() {
+            Some((budget, writer)) => {
+                let len = buf.len().min(*budget);
+                let n = writer.write(&buf[..len])?;
+                *budget -= n;
+                Ok(n)
+            },
+            None => {
+                Ok(0)
+            },
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for (_, writer) in self.writers.iter_mut() {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::BlockingWriter;
+
+    #[test]
+    fn write_spans_boundary() {
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        {
+            let mut consumer = ChainConsumer::new(vec![
+                (2, Box::new(&mut header)),
+                (usize::MAX, Box::new(&mut body)),
+            ]);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65, 66, 67]), Ok(3));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[68]), Ok(1));
+        }
+        assert_eq!(header, vec![65, 66]);
+        assert_eq!(body, vec![67, 68]);
+    }
+
+    #[test]
+    fn blocked_second_writer_buffers_remainder() {
+        let mut header = Vec::new();
+        let mut body = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = ChainConsumer::new(vec![
+                (1, Box::new(&mut header)),
+                (usize::MAX, Box::new(&mut body)),
+            ]);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+            assert_eq!(consumer.pending_bytes(), 2);
+            assert_eq!(consumer.demand(), 0);
+
+            consumer.update();
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.pending_bytes(), 0);
+        }
+        assert_eq!(header, vec![65]);
+        assert_eq!(body.data, vec![66, 67]);
+    }
+
+    #[test]
+    fn exhausted_chain_terminates() {
+        let mut consumer = ChainConsumer::new(vec![(1, Box::new(Vec::new()))]);
+        assert_eq!(consumer.write(&[65, 66]), Err(ConsumerError::WriteZero));
+    }
+}
@@ -0,0 +1,17 @@
+use std::time::Instant;
+
+
+// Where a consumer gets the current time from. Swapping in a different clock
+// makes anything time-based deterministic in tests.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::Write;
+use std::ops::{Deref, DerefMut};
+
+use crate::{ConsumerError, WriteAdapterConsumer};
+
+
+// Borrows a consumer and calls flush_all() on it when dropped, so buffered
+// data reaches the writer by the end of the scope. Errors are ignored on
+// drop; call drain() instead to see them.
+pub struct DrainGuard<'g, 'a, W: Write> {
+    consumer: &'g mut WriteAdapterConsumer<'a, W>,
+    drained: bool,
+}
+
+impl<'g, 'a, W: Write> DrainGuard<'g, 'a, W> {
+    pub(crate) fn new(consumer: &'g mut WriteAdapterConsumer<'a, W>) -> DrainGuard<'g, 'a, W> {
+        DrainGuard {
+            consumer,
+            drained: false,
+        }
+    }
+
+    // Does what dropping the guard would, but hands back the result.
+    pub fn drain(mut self) -> Result<(), ConsumerError> {
+        self.drained = true;
+        self.consumer.flush_all()
+    }
+}
+
+impl<'g, 'a, W: Write> Deref for DrainGuard<'g, 'a, W> {
+    type Target = WriteAdapterConsumer<'a, W>;
+
+    fn deref(&self) -> &WriteAdapterConsumer<'a, W> {
+        self.consumer
+    }
+}
+
+impl<'g, 'a, W: Write> DerefMut for DrainGuard<'g, 'a, W> {
+    fn deref_mut(&mut self) -> &mut WriteAdapterConsumer<'a, W> {
+        self.consumer
+    }
+}
+
+impl<'g, 'a, W: Write> Drop for DrainGuard<'g, 'a, W> {
+    fn drop(&mut self) {
+        if !self.drained {
+            let _ = self.consumer.flush_all();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io;
+    use crate::test_util::BlockingWriter;
+
+    #[test]
+    fn drains_on_drop() {
+        let mut consumer = WriteAdapterConsumer::new(BlockingWriter{ blocks: 1, data: Vec::new() });
+        {
+            let mut guard = consumer.drain_guard();
+            assert_eq!(guard.write(&[65, 66]), Ok(0));
+            assert_eq!(guard.pending_bytes(), 2);
+        }
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.get_ref().data, vec![65, 66]);
+    }
+
+    #[test]
+    fn drain_reports_the_result() {
+        let mut consumer = WriteAdapterConsumer::new(BlockingWriter{ blocks: 2, data: Vec::new() });
+        let mut guard = consumer.drain_guard();
+        assert_eq!(guard.write(&[65]), Ok(0));
+        assert_eq!(guard.drain(), Err(ConsumerError::Io(io::ErrorKind::WouldBlock.into())));
+        assert_eq!(consumer.pending_bytes(), 1);
+    }
+}
@@ -0,0 +1,104 @@
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use crate::{BoxedWriteAdapterConsumer, Consumer, ConsumerError, ConsumerEvent, UpdateResult, WriteAdapterConsumer};
+
+
+// Sends each write as a frame: a 4-byte big-endian length followed by the
+// payload. Header and payload make up a single unit of demand, so they're
+// buffered together if the writer doesn't take the whole frame.
+pub struct FramedConsumer<'a> {
+    inner: BoxedWriteAdapterConsumer<'a>,
+}
+
+impl<'a> FramedConsumer<'a> {
+    pub fn new<T: 'a + Write>(writer: T) -> FramedConsumer<'a> {
+        FramedConsumer {
+            inner: WriteAdapterConsumer::new(Box::new(writer)),
+        }
+    }
+
+    pub fn demand(&self) -> usize {
+        self.inner.demand()
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    // Ends the stream without writing a frame of its own.
+    pub fn finish(&mut self) -> Result<(), ConsumerError> {
+        self.inner.finish()
+    }
+}
+
+impl<'a> Consumer<[u8]> for FramedConsumer<'a> {
+    // Returns the number of frame bytes written, header included.
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        if data.is_empty() {
+            return self.finish().map(|_| 0);
+        }
+
+        let len = u32::try_from(data.len()).map_err(|_| {
+            ConsumerError::Io(io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))
+        })?;
+
+        self.inner.write_many(&[&len.to_be_bytes(), data])
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        self.inner.emit(event)
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.inner.next_event()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        self.inner.update()
+    }
+
+    fn request(&mut self, n: usize) {
+        self.inner.request(n);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::PartialWriter;
+
+    #[test]
+    fn frames_are_length_prefixed() {
+        let mut output = Vec::new();
+        {
+            let mut consumer = FramedConsumer::new(&mut output);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(b"ab"), Ok(6));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(b"c"), Ok(5));
+            assert_eq!(consumer.finish(), Ok(()));
+        }
+        assert_eq!(output, b"\x00\x00\x00\x02ab\x00\x00\x00\x01c");
+    }
+
+    #[test]
+    fn partial_frame_is_buffered() {
+        let mut writer = PartialWriter::default();
+        {
+            let mut consumer = FramedConsumer::new(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(b"ab"), Ok(1));
+            assert_eq!(consumer.pending_bytes(), 5);
+            assert_eq!(consumer.demand(), 0);
+
+            while consumer.pending_bytes() > 0 {
+                consumer.update();
+            }
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        }
+        assert_eq!(writer.data, b"\x00\x00\x00\x02ab");
+    }
+}
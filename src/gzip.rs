@@ -0,0 +1,135 @@
+use std::io::{self, Write};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::{Consumer, ConsumerError, ConsumerEvent, UpdateResult, WriteAdapterConsumer};
+
+
+// Gzips everything on its way to the writer. The encoder sits below the
+// demand handling, so writes, buffering and byte counts all work as with the
+// plain adapter and refer to the uncompressed data.
+pub struct GzipConsumer<'a, W: Write = Box<dyn Write + 'a>> {
+    inner: WriteAdapterConsumer<'a, GzWriter<W>>,
+}
+
+struct GzWriter<W: Write> {
+    encoder: GzEncoder<W>,
+    // The encoder mustn't be used once the trailer has been written, so from
+    // then on flushes go straight to the writer.
+    trailer_written: bool,
+}
+
+impl<'a, W: Write> GzipConsumer<'a, W> {
+    pub fn new(writer: W) -> GzipConsumer<'a, W> {
+        GzipConsumer::with_level(writer, Compression::default())
+    }
+
+    pub fn with_level(writer: W, level: Compression) -> GzipConsumer<'a, W> {
+        GzipConsumer {
+            inner: WriteAdapterConsumer::new(GzWriter {
+                encoder: GzEncoder::new(writer, level),
+                trailer_written: false,
+            }),
+        }
+    }
+
+    pub fn demand(&self) -> usize {
+        self.inner.demand()
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.inner.pending_bytes()
+    }
+
+    // Writes out anything buffered, then the end of the gzip stream, before
+    // finishing the inner consumer. If the writer blocks along the way this
+    // fails with WouldBlock and can be retried.
+    pub fn finish(&mut self) -> Result<(), ConsumerError> {
+        self.inner.flush_all()?;
+
+        if let Err(err) = self.inner.get_mut().encoder.try_finish() {
+            if err.kind() == io::ErrorKind::WouldBlock {
+                return Err(ConsumerError::Io(err));
+            }
+
+            return Err(self.inner.terminate_with(err));
+        }
+
+        self.inner.get_mut().trailer_written = true;
+        self.inner.finish()
+    }
+
+    // Gives back the writer once the gzip stream has been completed.
+    pub fn into_inner(self) -> io::Result<W> {
+        self.inner.into_inner().encoder.finish()
+    }
+}
+
+impl<'a, W: Write> Consumer<[u8]> for GzipConsumer<'a, W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        if data.is_empty() {
+            return self.finish().map(|_| 0);
+        }
+
+        self.inner.write(data)
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        self.inner.emit(event)
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.inner.next_event()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        self.inner.update()
+    }
+
+    fn request(&mut self, n: usize) {
+        self.inner.request(n);
+    }
+}
+
+impl<W: Write> Write for GzWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.trailer_written {
+            self.encoder.get_mut().flush()
+        }
+        else {
+            self.encoder.flush()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Read;
+    use flate2::read::GzDecoder;
+
+    #[test]
+    fn round_trip() {
+        let payload = b"hello hello hello hello gzip".to_vec();
+
+        let mut consumer = GzipConsumer::new(Vec::new());
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&payload), Ok(payload.len()));
+        assert_eq!(consumer.write(&[]), Ok(0));
+        assert!(consumer.collect_events().contains(&ConsumerEvent::Finish));
+
+        let compressed = consumer.into_inner().unwrap();
+        assert_ne!(compressed, payload);
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, payload);
+    }
+}
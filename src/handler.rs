@@ -0,0 +1,112 @@
+use std::io;
+
+use crate::{ConsumerEvent, TerminationReason};
+
+
+// Callbacks for each kind of event, for drivers that would rather not match
+// on ConsumerEvent themselves. See Consumer::dispatch(). Only the events
+// that end the stream or hand out demand have to be handled; the others
+// default to doing nothing.
+pub trait EventHandler {
+    fn on_request(&mut self, n: usize);
+    fn on_termination(&mut self, reason: TerminationReason, unacked: usize);
+    fn on_finish(&mut self);
+
+    fn on_flush(&mut self) {
+    }
+
+    fn on_drained(&mut self) {
+    }
+
+    fn on_error(&mut self, _kind: io::ErrorKind) {
+    }
+
+    fn on_high_water(&mut self) {
+    }
+
+    fn on_low_water(&mut self) {
+    }
+
+    fn handle(&mut self, event: ConsumerEvent) {
+        match event {
+            ConsumerEvent::Request(n) => {
+                self.on_request(n);
+            },
+            ConsumerEvent::Termination { reason, unacked } => {
+                self.on_termination(reason, unacked);
+            },
+            ConsumerEvent::Finish => {
+                self.on_finish();
+            },
+            ConsumerEvent::Flush => {
+                self.on_flush();
+            },
+            ConsumerEvent::Drained => {
+                self.on_drained();
+            },
+            ConsumerEvent::Error(kind) => {
+                self.on_error(kind);
+            },
+            ConsumerEvent::HighWater => {
+                self.on_high_water();
+            },
+            ConsumerEvent::LowWater => {
+                self.on_low_water();
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{Consumer, WriteAdapterConsumer};
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        calls: Vec<String>,
+    }
+
+    impl EventHandler for RecordingHandler {
+        fn on_request(&mut self, n: usize) {
+            self.calls.push(format!("request {}", n));
+        }
+
+        fn on_termination(&mut self, reason: TerminationReason, unacked: usize) {
+            self.calls.push(format!("termination {:?} {}", reason, unacked));
+        }
+
+        fn on_finish(&mut self) {
+            self.calls.push("finish".to_string());
+        }
+
+        fn on_flush(&mut self) {
+            self.calls.push("flush".to_string());
+        }
+    }
+
+    #[test]
+    fn dispatches_in_order() {
+        let mut consumer = WriteAdapterConsumer::new(Vec::new());
+        let mut handler = RecordingHandler::default();
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.flush(), Ok(()));
+        consumer.dispatch(&mut handler);
+        assert_eq!(consumer.next_event(), None);
+
+        assert_eq!(consumer.write(&[]), Ok(0));
+        consumer.dispatch(&mut handler);
+        assert_eq!(handler.calls, vec!["request 2", "flush", "finish"]);
+    }
+
+    #[test]
+    fn termination_is_dispatched() {
+        let mut consumer = WriteAdapterConsumer::new(Vec::new());
+        let mut handler = RecordingHandler::default();
+        consumer.cancel();
+        consumer.dispatch(&mut handler);
+        assert_eq!(handler.calls, vec!["request 1", "termination Cancelled 0"]);
+    }
+}
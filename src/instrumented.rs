@@ -0,0 +1,146 @@
+use std::io::{self, IoSlice, Write};
+
+use crate::{Consumer, ConsumerError, ConsumerEvent, UpdateResult, WriteAdapterConsumer};
+
+
+// A WriteAdapterConsumer that keeps count of what reaches its writer, so
+// tests can check how a sink was driven without writing their own mock.
+pub struct InstrumentedConsumer<'a, W: Write> {
+    inner: WriteAdapterConsumer<'a, CountingWriter<W>>,
+}
+
+struct CountingWriter<W: Write> {
+    writer: W,
+    write_calls: usize,
+    bytes: u64,
+    flushes: usize,
+}
+
+impl<'a, W: Write> InstrumentedConsumer<'a, W> {
+    pub fn new(writer: W) -> InstrumentedConsumer<'a, W> {
+        InstrumentedConsumer {
+            inner: WriteAdapterConsumer::new(CountingWriter {
+                writer,
+                write_calls: 0,
+                bytes: 0,
+                flushes: 0,
+            }),
+        }
+    }
+
+    // Calls to the writer's write or write_vectored, including ones that
+    // failed or blocked.
+    pub fn write_calls(&self) -> usize {
+        self.counts().write_calls
+    }
+
+    // Bytes the writer accepted.
+    pub fn bytes(&self) -> u64 {
+        self.counts().bytes
+    }
+
+    pub fn flushes(&self) -> usize {
+        self.counts().flushes
+    }
+
+    pub fn demand(&self) -> usize {
+        self.inner.demand()
+    }
+
+    pub fn write_many(&mut self, bufs: &[&[u8]]) -> Result<usize, ConsumerError> {
+        self.inner.write_many(bufs)
+    }
+
+    pub fn flush(&mut self) -> Result<(), ConsumerError> {
+        self.inner.flush()
+    }
+
+    pub fn finish(&mut self) -> Result<(), ConsumerError> {
+        self.inner.finish()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner.into_inner().writer
+    }
+
+    fn counts(&self) -> &CountingWriter<W> {
+        self.inner.writer.as_ref().expect("writer taken by into_inner")
+    }
+}
+
+impl<'a, W: Write> Consumer<[u8]> for InstrumentedConsumer<'a, W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        self.inner.write(data)
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        self.inner.emit(event)
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.inner.next_event()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        self.inner.update()
+    }
+
+    fn request(&mut self, n: usize) {
+        self.inner.request(n);
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_calls += 1;
+        let n = self.writer.write(buf)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    // Forwarded so the consumer drives the writer the same way it would
+    // without the counting in between. is_write_vectored() would be too, but
+    // it isn't stable yet.
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        self.write_calls += 1;
+        let n = self.writer.write_vectored(bufs)?;
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushes += 1;
+        self.writer.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn counts_writes_bytes_and_flushes() {
+        let mut consumer = InstrumentedConsumer::new(Vec::new());
+        assert_eq!(consumer.write(&[65, 66]), Ok(2));
+        assert_eq!(consumer.write(&[67]), Ok(1));
+        assert_eq!(consumer.flush(), Ok(()));
+        assert_eq!(consumer.finish(), Ok(()));
+
+        assert_eq!(consumer.write_calls(), 2);
+        assert_eq!(consumer.bytes(), 3);
+        assert_eq!(consumer.flushes(), 2);
+        assert_eq!(consumer.into_inner(), vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn vectored_writes_count_once() {
+        let mut consumer = InstrumentedConsumer::new(Vec::new());
+        assert_eq!(consumer.write_many(&[&[65, 66], &[67]]), Ok(3));
+
+        assert_eq!(consumer.write_calls(), 1);
+        assert_eq!(consumer.bytes(), 3);
+        assert_eq!(consumer.into_inner(), vec![65, 66, 67]);
+    }
+}
@@ -0,0 +1,81 @@
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::Clock;
+
+
+// How long the writer's write calls have taken so far.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub total: Duration,
+}
+
+impl LatencyStats {
+    pub fn average(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        }
+        else {
+            self.total.div_f64(self.count as f64)
+        }
+    }
+}
+
+// Times each call by reading the clock on either side of it.
+pub(crate) struct LatencyTimer {
+    clock: Rc<dyn Clock>,
+    stats: Option<LatencyStats>,
+}
+
+impl LatencyTimer {
+    pub(crate) fn new(clock: Rc<dyn Clock>) -> LatencyTimer {
+        LatencyTimer {
+            clock,
+            stats: None,
+        }
+    }
+
+    pub(crate) fn start(&self) -> Instant {
+        self.clock.now()
+    }
+
+    pub(crate) fn stop(&mut self, started: Instant) {
+        let elapsed = self.clock.now().saturating_duration_since(started);
+
+        self.stats = Some(match self.stats {
+            Some(stats) => LatencyStats {
+                count: stats.count + 1,
+                min: stats.min.min(elapsed),
+                max: stats.max.max(elapsed),
+                total: stats.total.saturating_add(elapsed),
+            },
+            None => LatencyStats {
+                count: 1,
+                min: elapsed,
+                max: elapsed,
+                total: elapsed,
+            },
+        });
+    }
+
+    // None until the first call has been timed.
+    pub(crate) fn stats(&self) -> Option<LatencyStats> {
+        self.stats
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn average_of_nothing_is_zero() {
+        let stats = LatencyStats{ count: 0, min: Duration::ZERO, max: Duration::ZERO, total: Duration::ZERO };
+        assert_eq!(stats.average(), Duration::ZERO);
+    }
+}
@@ -1,188 +1,3583 @@
-use std::io::Write;
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+mod async_core;
+#[cfg(feature = "async-std")]
+mod async_std_adapter;
+mod backoff;
+mod broadcast;
+mod chain;
+mod clock;
+mod drain_guard;
+mod framed;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod handler;
+#[cfg(feature = "testing")]
+mod instrumented;
+mod latency;
+mod line;
+mod map;
+mod producer;
+mod rate;
+mod readable;
+mod scheduler;
+#[cfg(feature = "serde")]
+mod serde_support;
+mod shared;
+mod sink;
+mod tee;
+#[cfg(test)]
+pub(crate) mod test_util;
+mod unit;
+#[cfg(feature = "tokio")]
+mod tokio_adapter;
+
+use std::io::{self, Cursor, IoSlice, Read, Seek, SeekFrom, Stdout, Write};
+use std::fs::File;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::collections::VecDeque;
-//use std::{thread, time};
+use std::collections::vec_deque::Drain;
+use std::iter;
+use std::mem;
+use std::error;
+use std::fmt;
+
+use backoff::Backoff;
+use latency::LatencyTimer;
+use rate::RateLimiter;
+use unit::Unit;
+
+#[cfg(feature = "async-std")]
+pub use async_std_adapter::AsyncStdWriteAdapterConsumer;
+pub use broadcast::Broadcaster;
+pub use chain::ChainConsumer;
+pub use clock::{Clock, SystemClock};
+pub use drain_guard::DrainGuard;
+pub use framed::FramedConsumer;
+#[cfg(feature = "gzip")]
+pub use gzip::GzipConsumer;
+pub use handler::EventHandler;
+#[cfg(feature = "testing")]
+pub use instrumented::InstrumentedConsumer;
+pub use latency::LatencyStats;
+pub use line::LineConsumer;
+pub use map::MapConsumer;
+pub use producer::{Producer, ProducerEvent, ReadAdapterProducer};
+pub use readable::ReadableConsumer;
+pub use scheduler::Scheduler;
+pub use shared::SharedWriter;
+pub use sink::SinkConsumer;
+pub use tee::TeeConsumer;
+#[cfg(feature = "tokio")]
+pub use tokio_adapter::AsyncWriteAdapterConsumer;
 
 
-#[derive(Debug, PartialEq)]
-enum ConsumerEvent {
+// Events are ordered so tests can sort what they collected and compare it
+// regardless of the order it was emitted in.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum ConsumerEvent {
     Request(usize),
-    Termination,
+    // unacked is how many units the consumer took demand for without ever
+    // giving it back with a Request, e.g. writes that were still buffered.
+    Termination { reason: TerminationReason, unacked: usize },
     Finish,
+    Flush,
+    // Everything that was buffered has now reached the writer.
+    Drained,
+    // Writing buffered data failed in update(), which has no other way of
+    // reporting it. The Termination that follows carries the same kind.
+    #[cfg_attr(feature = "serde", serde(with = "serde_support::error_kind"))]
+    Error(io::ErrorKind),
+    // The buffer has filled up past the high watermark. Worth pausing until
+    // LowWater.
+    HighWater,
+    // After a HighWater, the buffer has emptied out below the low watermark.
+    LowWater,
 }
 
-#[derive(Debug, PartialEq)]
-enum ConsumerError {
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum TerminationReason {
+    Cancelled,
+    UpstreamError,
+    #[cfg_attr(feature = "serde", serde(with = "serde_support::error_kind"))]
+    WriteError(io::ErrorKind),
+}
+
+#[derive(Debug)]
+pub enum ConsumerError {
     WriteWithoutRequest,
+    BufferFull,
+    // The stream has already finished or been terminated.
+    Terminated,
+    WriteZero,
+    // The write would take the stream past its byte limit.
+    LimitReached,
+    // write_before() gave up waiting for demand.
+    Timeout,
+    Io(io::Error),
+}
+
+impl PartialEq for ConsumerError {
+    fn eq(&self, other: &ConsumerError) -> bool {
+        match (self, other) {
+            (ConsumerError::Io(a), ConsumerError::Io(b)) => a.kind() == b.kind(),
+            (a, b) => mem::discriminant(a) == mem::discriminant(b),
+        }
+    }
+}
+
+impl fmt::Display for ConsumerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConsumerError::WriteWithoutRequest => write!(f, "write without request"),
+            ConsumerError::BufferFull => write!(f, "buffer full"),
+            ConsumerError::Terminated => write!(f, "consumer terminated"),
+            ConsumerError::WriteZero => write!(f, "writer accepted zero bytes"),
+            ConsumerError::LimitReached => write!(f, "byte limit reached"),
+            ConsumerError::Timeout => write!(f, "deadline passed before demand"),
+            ConsumerError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for ConsumerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConsumerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Lets consumer calls use ? in functions returning io::Result. A missing
+// Request is WouldBlock, since retrying after one will work.
+impl From<ConsumerError> for io::Error {
+    fn from(err: ConsumerError) -> io::Error {
+        match err {
+            ConsumerError::WriteWithoutRequest => io::Error::new(io::ErrorKind::WouldBlock, "write without request"),
+            ConsumerError::BufferFull => io::Error::other("buffer full"),
+            ConsumerError::Terminated => io::Error::new(io::ErrorKind::BrokenPipe, "consumer terminated"),
+            ConsumerError::WriteZero => io::ErrorKind::WriteZero.into(),
+            ConsumerError::LimitReached => io::Error::other("byte limit reached"),
+            ConsumerError::Timeout => io::Error::new(io::ErrorKind::TimedOut, "deadline passed before demand"),
+            ConsumerError::Io(e) => e,
+        }
+    }
+}
+
+// What became of the data handed to try_write().
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WriteOutcome {
+    // The writer took all of it.
+    Accepted(usize),
+    // There was no demand, so nothing was written. Try again after the next
+    // Request.
+    NeedRequest,
+    // Some or all of it is waiting in the buffer for update().
+    Buffered,
 }
 
-trait Consumer {
-    fn write(&mut self, data: &[u8]) -> Result<(), ConsumerError>;
-    fn emit(&mut self, event: ConsumerEvent);
+// What a call to update() got done.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UpdateResult {
+    // Nothing reached the writer.
+    Idle,
+    // bytes reached the writer, and more is set if some of the buffer is
+    // still waiting.
+    Progressed { bytes: usize, more: bool },
+}
+
+impl UpdateResult {
+    // Adds up the results from updating two consumers.
+    pub(crate) fn combine(self, other: UpdateResult) -> UpdateResult {
+        match (self, other) {
+            (UpdateResult::Idle, result) | (result, UpdateResult::Idle) => {
+                result
+            },
+            (UpdateResult::Progressed { bytes: a, more: more_a }, UpdateResult::Progressed { bytes: b, more: more_b }) => {
+                UpdateResult::Progressed { bytes: a + b, more: more_a || more_b }
+            },
+        }
+    }
+}
+
+pub trait Consumer<T: ?Sized = [u8]> {
+    fn write(&mut self, data: &T) -> Result<usize, ConsumerError>;
+    // Returns false if the event couldn't be queued.
+    fn emit(&mut self, event: ConsumerEvent) -> bool;
     fn next_event(&mut self) -> Option<ConsumerEvent>;
-    fn update(&mut self);
+    fn update(&mut self) -> UpdateResult;
+
+    // Takes every event currently queued, oldest first.
+    fn collect_events(&mut self) -> Vec<ConsumerEvent> {
+        iter::from_fn(|| self.next_event()).collect()
+    }
+
+    // Takes every event currently queued, oldest first, and passes each one
+    // to the matching handler callback.
+    fn dispatch(&mut self, handler: &mut impl EventHandler) {
+        while let Some(event) = self.next_event() {
+            handler.handle(event);
+        }
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<(), ConsumerError> where str: AsRef<T> {
+        self.write(s.as_ref()).map(|_| ())
+    }
+
+    // Grants n more units of demand, for consumers that let the caller do
+    // that. The default does nothing.
+    fn request(&mut self, _n: usize) {
+    }
+
+    // Writes data without the caller having to track demand: if there isn't
+    // any, it's requested, and then update() is driven for as long as there's
+    // more buffered data it can get out. With a blocking writer that means all
+    // of data has reached the writer on return. A non-blocking one can leave
+    // some buffered, which later updates pick up as usual.
+    fn write_all(&mut self, data: &[u8]) -> Result<(), ConsumerError> where [u8]: AsRef<T> {
+        // An empty write would finish the stream.
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let mut requested = false;
+
+        loop {
+            match self.write(data.as_ref()) {
+                Ok(_) => {
+                    break;
+                },
+                Err(ConsumerError::WriteWithoutRequest) if !requested => {
+                    self.request(1);
+                    requested = true;
+                },
+                Err(ConsumerError::WriteWithoutRequest) => {
+                    // Requesting didn't help, so the demand can only come
+                    // from the buffer draining.
+                    if self.update() == UpdateResult::Idle {
+                        return Err(ConsumerError::WriteWithoutRequest);
+                    }
+                },
+                Err(e) => {
+                    return Err(e);
+                },
+            }
+        }
+
+        while let UpdateResult::Progressed { more: true, .. } = self.update() {
+        }
+
+        Ok(())
+    }
 }
 
 
-pub struct WriteAdapterConsumer<'a> {
-    writer: Box<'a + Write>,
+// Generic over the writer so it can be handed back by into_inner(). The
+// default keeps the boxed form for anyone who doesn't care about the type.
+pub struct WriteAdapterConsumer<'a, W: Write = Box<dyn Write + 'a>> {
+    // Only None once into_inner() has taken it.
+    writer: Option<W>,
     demand: usize,
     event_queue: VecDeque<ConsumerEvent>,
-    buffered: Option<Vec<u8>>,
+    // One entry per unit of demand that's still waiting on the writer.
+    buffered: VecDeque<Unit>,
+    // Units the writer has finished with while others were still buffered.
+    // Their demand is held back until the buffer is empty.
+    withheld: usize,
+    batched: usize,
+    // Data from write_priority() that's still waiting. It goes out before
+    // anything in buffered and doesn't count against demand.
+    priority: VecDeque<Unit>,
+    // The writer has taken part of the front buffered unit, so nothing else
+    // can be written until it has the rest.
+    front_started: bool,
+    capacity: Option<usize>,
+    chunk_size: Option<usize>,
+    max_events: Option<usize>,
+    request_granularity: usize,
+    max_demand: Option<usize>,
+    coalesce_requests: bool,
+    request_batch: Option<usize>,
+    io_chunk_size: Option<usize>,
+    // High and low watermarks in bytes, and whether HighWater was the last
+    // of the two to be emitted.
+    watermarks: Option<(usize, usize)>,
+    above_high_water: bool,
+    rate_limit: Option<RateLimiter>,
+    backoff: Option<Backoff>,
+    latency: Option<LatencyTimer>,
+    clock: Rc<dyn Clock>,
+    byte_limit: Option<u64>,
+    closed: bool,
+    cancelled: bool,
+    bytes_written: u64,
+    on_write: Option<Box<dyn FnMut(usize) + 'a>>,
+    on_error: Option<ErrorCallback<'a>>,
+    // Running totals of the demand handed out and taken back, so debug builds
+    // can check that demand always matches the difference.
+    #[cfg(debug_assertions)]
+    granted: u128,
+    #[cfg(debug_assertions)]
+    used: u128,
+}
+
+type ErrorCallback<'a> = Box<dyn FnMut(&io::Error) + 'a>;
+
+// A snapshot of a consumer's state, e.g. for logging.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Stats {
+    pub demand: usize,
+    pub pending_bytes: usize,
+    pub bytes_written: u64,
+    pub queued_events: usize,
+    pub closed: bool,
+}
+
+// The type-erased form, for when the concrete writer type doesn't matter or
+// can't be named.
+pub type BoxedWriteAdapterConsumer<'a> = WriteAdapterConsumer<'a, Box<dyn Write + 'a>>;
+
+pub struct WriteAdapterConsumerBuilder {
+    demand: usize,
+    capacity: Option<usize>,
+    chunk_size: Option<usize>,
+    max_events: Option<usize>,
+    request_granularity: usize,
+    max_demand: Option<usize>,
+    coalesce_requests: bool,
+    request_batch: Option<usize>,
+    io_chunk_size: Option<usize>,
+    watermarks: Option<(usize, usize)>,
+    rate_limit: Option<u64>,
+    clock: Option<Rc<dyn Clock>>,
+    time_writes: bool,
+    backoff: Option<(Duration, Duration)>,
+    byte_limit: Option<u64>,
 }
 
-impl<'a> WriteAdapterConsumer<'a> {
-    pub fn new<T: 'a + Write>(writer: T) -> WriteAdapterConsumer<'a> {
+impl WriteAdapterConsumerBuilder {
+    pub fn new() -> WriteAdapterConsumerBuilder {
+        WriteAdapterConsumerBuilder {
+            demand: 1,
+            capacity: None,
+            chunk_size: None,
+            max_events: None,
+            request_granularity: 1,
+            max_demand: None,
+            coalesce_requests: true,
+            request_batch: None,
+            io_chunk_size: None,
+            watermarks: None,
+            rate_limit: None,
+            clock: None,
+            time_writes: false,
+            backoff: None,
+            byte_limit: None,
+        }
+    }
+
+    pub fn demand(mut self, demand: usize) -> WriteAdapterConsumerBuilder {
+        self.demand = demand;
+        self
+    }
+
+    // Refuse data that could leave more than capacity bytes buffered if the
+    // writer doesn't take it.
+    pub fn capacity(mut self, capacity: usize) -> WriteAdapterConsumerBuilder {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    // Split each write into pieces of at most chunk_size bytes. Every piece
+    // is its own unit of demand, so a write needs as much demand as it has
+    // pieces, and a Request(1) follows each piece the writer fully accepts.
+    pub fn chunk_size(mut self, chunk_size: usize) -> WriteAdapterConsumerBuilder {
+        assert!(chunk_size > 0, "chunk_size must be non-zero");
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    // Limit how many events can sit in the queue. Once it's full, requests
+    // are folded into the newest queued request, and Drained, HighWater,
+    // LowWater and Flush are refused. Anything else still gets queued, in
+    // place of one of those if there is one, since losing a request or the
+    // end of the stream would leave the driver stuck.
+    pub fn max_events(mut self, max_events: usize) -> WriteAdapterConsumerBuilder {
+        self.max_events = Some(max_events);
+        self
+    }
+
+    // How much demand to grant each time the writer completes a unit.
+    pub fn request_granularity(mut self, n: usize) -> WriteAdapterConsumerBuilder {
+        self.request_granularity = n;
+        self
+    }
+
+    // Never let demand grow past max, however much is requested. Requests
+    // only report the demand that was actually added.
+    pub fn max_demand(mut self, max: usize) -> WriteAdapterConsumerBuilder {
+        self.max_demand = Some(max);
+        self
+    }
+
+    // Merge a Request into the one before it when nothing else was queued in
+    // between. On by default, which is how requests have always been
+    // emitted. Turn it off to see every request as its own event.
+    pub fn coalesce_requests(mut self, coalesce: bool) -> WriteAdapterConsumerBuilder {
+        self.coalesce_requests = coalesce;
+        self
+    }
+
+    // Hold back the demand for completed units until n of them have built up
+    // or update() is called, then hand it all out in a single Request. Saves
+    // drivers from pumping one unit at a time.
+    pub fn request_batch(mut self, n: usize) -> WriteAdapterConsumerBuilder {
+        self.request_batch = Some(n);
+        self
+    }
+
+    // Hand the writer at most io_chunk_size bytes per call, however big the
+    // write. Unlike chunk_size this has nothing to do with demand: a write is
+    // still one unit, it just reaches the writer in pieces.
+    pub fn io_chunk_size(mut self, io_chunk_size: usize) -> WriteAdapterConsumerBuilder {
+        assert!(io_chunk_size > 0, "io_chunk_size must be non-zero");
+        self.io_chunk_size = Some(io_chunk_size);
+        self
+    }
+
+    // Emit HighWater once at least high bytes are buffered, and LowWater
+    // once that drops back below low. With a capacity set, these default to
+    // 75% and 25% of it.
+    pub fn watermarks(mut self, high: usize, low: usize) -> WriteAdapterConsumerBuilder {
+        assert!(low <= high, "low watermark must not be above the high one");
+        self.watermarks = Some((high, low));
+        self
+    }
+
+    // Let at most bytes_per_sec bytes through to the writer each second.
+    // Whatever goes over is buffered until enough time has passed.
+    pub fn rate_limit(mut self, bytes_per_sec: u64) -> WriteAdapterConsumerBuilder {
+        assert!(bytes_per_sec > 0, "bytes_per_sec must be non-zero");
+        self.rate_limit = Some(bytes_per_sec);
+        self
+    }
+
+    // The clock the rate limit, write timings and write_before() deadlines
+    // are measured against. Defaults to SystemClock.
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> WriteAdapterConsumerBuilder {
+        self.clock = Some(Rc::new(clock));
+        self
+    }
+
+    // Time every call into the writer, see write_latency_stats(). Off by
+    // default, in which case the clock is never read for this.
+    pub fn time_writes(mut self) -> WriteAdapterConsumerBuilder {
+        self.time_writes = true;
+        self
+    }
+
+    // Track how many attempts in a row the writer has blocked, so that
+    // next_retry_after() can suggest waiting base, then twice that and so
+    // on, up to max.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> WriteAdapterConsumerBuilder {
+        self.backoff = Some((base, max));
+        self
+    }
+
+    // Accept at most max bytes in total. A write that would go over is
+    // refused with LimitReached, and reaching the limit exactly finishes the
+    // stream once everything has been written out.
+    pub fn byte_limit(mut self, max: u64) -> WriteAdapterConsumerBuilder {
+        self.byte_limit = Some(max);
+        self
+    }
 
-        let initial_demand = 1;
+    pub fn build<'a, W: Write>(self, writer: W) -> WriteAdapterConsumer<'a, W> {
+        let watermarks = self.watermarks.or_else(|| self.capacity.map(|cap| (cap * 3 / 4, cap / 4)));
+        let clock = self.clock.unwrap_or_else(|| Rc::new(SystemClock));
+        let rate_limit = self.rate_limit.map(|rate| RateLimiter::new(rate, clock.clone()));
+        let latency = if self.time_writes { Some(LatencyTimer::new(clock.clone())) } else { None };
 
         let mut consumer = WriteAdapterConsumer {
-            writer: Box::new(writer),
-            demand: initial_demand,
+            writer: Some(writer),
+            demand: 0,
             event_queue: VecDeque::new(),
-            buffered: None,
+            buffered: VecDeque::new(),
+            withheld: 0,
+            batched: 0,
+            priority: VecDeque::new(),
+            front_started: false,
+            capacity: self.capacity,
+            chunk_size: self.chunk_size,
+            max_events: self.max_events,
+            request_granularity: self.request_granularity,
+            max_demand: self.max_demand,
+            coalesce_requests: self.coalesce_requests,
+            request_batch: self.request_batch,
+            io_chunk_size: self.io_chunk_size,
+            watermarks,
+            above_high_water: false,
+            rate_limit,
+            backoff: self.backoff.map(|(base, max)| Backoff::new(base, max)),
+            latency,
+            clock,
+            byte_limit: self.byte_limit,
+            closed: false,
+            cancelled: false,
+            bytes_written: 0,
+            on_write: None,
+            on_error: None,
+            #[cfg(debug_assertions)]
+            granted: 0,
+            #[cfg(debug_assertions)]
+            used: 0,
         };
 
-        consumer.emit(ConsumerEvent::Request(initial_demand));
+        let added = consumer.grant(self.demand);
+        consumer.emit(ConsumerEvent::Request(added));
 
         consumer
     }
+}
 
+impl Default for WriteAdapterConsumerBuilder {
+    fn default() -> WriteAdapterConsumerBuilder {
+        WriteAdapterConsumerBuilder::new()
+    }
 }
 
-impl<'a> Consumer for WriteAdapterConsumer<'a> {
-    fn write(&mut self, data: &[u8]) -> Result<(), ConsumerError> {
-        if self.demand > 0 {
-            // TODO: handle case where only partial data is written
-            match self.writer.write(data) {
-                Ok(n) => {
-                    if n != data.len() {
-                        self.buffered = Some(data[n..].into());
-                        self.demand -= 1;
-                    }
-                    else {
-                        self.emit(ConsumerEvent::Request(1));
-                    }
+impl<'a, W: Write> WriteAdapterConsumer<'a, W> {
+    pub fn new(writer: W) -> WriteAdapterConsumer<'a, W> {
+        WriteAdapterConsumerBuilder::new().build(writer)
+    }
 
-                    Ok(())
-                },
-                Err(_) => {
-                    println!("getting buffed");
-                    self.buffered = Some(data.into());
-                    self.demand -= 1;
-                    Ok(())
-                },
+    // Every completed write asks for another initial_demand units, or one
+    // if that's zero, so a stream that starts out paused can still get going.
+    pub fn with_demand(writer: W, initial_demand: usize) -> WriteAdapterConsumer<'a, W> {
+        WriteAdapterConsumerBuilder::new()
+            .demand(initial_demand)
+            .request_granularity(initial_demand.max(1))
+            .build(writer)
+    }
+
+    pub fn with_capacity(writer: W, capacity: usize) -> WriteAdapterConsumer<'a, W> {
+        WriteAdapterConsumerBuilder::new().capacity(capacity).build(writer)
+    }
+
+    pub fn with_rate_limit(writer: W, bytes_per_sec: u64) -> WriteAdapterConsumer<'a, W> {
+        WriteAdapterConsumerBuilder::new().rate_limit(bytes_per_sec).build(writer)
+    }
+
+    pub fn with_byte_limit(writer: W, max: u64) -> WriteAdapterConsumer<'a, W> {
+        WriteAdapterConsumerBuilder::new().byte_limit(max).build(writer)
+    }
+
+    // Starts with data already waiting to go out, e.g. when resuming an
+    // interrupted transfer. No demand is handed out until update() has
+    // written all of it.
+    pub fn with_initial_buffer(writer: W, data: Vec<u8>) -> WriteAdapterConsumer<'a, W> {
+        if data.is_empty() {
+            return WriteAdapterConsumer::new(writer);
+        }
+
+        let mut consumer = WriteAdapterConsumerBuilder::new().demand(0).build(writer);
+        // Counts as a completed unit once it's out, which grants the usual
+        // first Request.
+        consumer.buffered.push_back(Unit::from(data));
+        consumer
+    }
+
+    // This is the Consumer::write implementation. It lives here so calls
+    // aren't ambiguous with io::Write::write when both traits are in scope.
+    // An empty write marks the end of the stream, the same as finish().
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        if data.is_empty() {
+            return self.finish().map(|_| 0);
+        }
+
+        let chunks: Vec<&[u8]> = match self.chunk_size {
+            Some(size) => data.chunks(size).collect(),
+            None => vec![data],
+        };
+
+        self.check_writable(data.len(), chunks.len())?;
+        self.catch_up()?;
+
+        let mut written = 0;
+        let mut chunks = chunks.into_iter();
+
+        while let Some(chunk) = chunks.next() {
+            let (n, err) = self.write_in_order(chunk);
+
+            if let Some(err) = err {
+                return Err(self.terminate_with(err));
+            }
+
+            written += n;
+            self.settle(chunk[n..].to_vec().into(), n > 0);
+
+            if n != chunk.len() {
+                for chunk in chunks {
+                    self.settle(chunk.to_vec().into(), false);
+                }
+                break;
             }
         }
-        else {
-            Err(ConsumerError::WriteWithoutRequest)
+
+        self.check_accounting();
+
+        Ok(written)
+    }
+
+    // Like write, but running out of demand is reported as an outcome rather
+    // than an error.
+    pub fn try_write(&mut self, data: &[u8]) -> Result<WriteOutcome, ConsumerError> {
+        match self.write(data) {
+            Ok(n) if n < data.len() => Ok(WriteOutcome::Buffered),
+            Ok(n) => Ok(WriteOutcome::Accepted(n)),
+            Err(ConsumerError::WriteWithoutRequest) => Ok(WriteOutcome::NeedRequest),
+            Err(err) => Err(err),
         }
     }
 
-    fn emit(&mut self, event: ConsumerEvent) {
-        self.event_queue.push_back(event);
+    // Like write, but takes ownership of the data so that whatever the writer
+    // doesn't accept can be buffered as is, without copying it.
+    pub fn write_owned(&mut self, data: Vec<u8>) -> Result<(), ConsumerError> {
+        if data.is_empty() || self.chunk_size.is_some() {
+            return self.write(&data).map(|_| ());
+        }
+
+        self.check_writable(data.len(), 1)?;
+        self.catch_up()?;
+        self.write_unit(data.into()).map(|_| ())
     }
 
-    fn next_event(&mut self) -> Option<ConsumerEvent> {
-        self.event_queue.pop_front()
+    // Like write_owned, for data that's already in a Bytes. Whatever the
+    // writer doesn't take is buffered as a slice of it.
+    #[cfg(feature = "bytes")]
+    pub fn write_bytes(&mut self, data: bytes::Bytes) -> Result<(), ConsumerError> {
+        if data.is_empty() || self.chunk_size.is_some() {
+            return self.write(&data).map(|_| ());
+        }
+
+        self.check_writable(data.len(), 1)?;
+        self.catch_up()?;
+        self.write_unit(Unit::Shared(data)).map(|_| ())
     }
 
-    fn update(&mut self) {
+    // Sends data ahead of anything already buffered, without needing or
+    // using up any demand. It's meant for small control messages. Units are
+    // still written whole, so if the writer has taken part of one the
+    // priority data waits until it has the rest.
+    pub fn write_priority(&mut self, data: &[u8]) -> Result<(), ConsumerError> {
+        if self.closed {
+            return Err(ConsumerError::Terminated);
+        }
+
+        let (n, err) = if self.priority.is_empty() && !self.front_started {
+            self.write_until_blocked(data)
+        }
+        else {
+            (0, None)
+        };
+
+        if let Some(err) = err {
+            return Err(self.terminate_with(err));
+        }
+
+        if n != data.len() {
+            self.priority.push_back(data[n..].to_vec().into());
+            self.check_watermarks();
+        }
+
+        Ok(())
     }
-}
 
+    // Writes bytes from iter in pieces of up to chunk bytes, one unit of
+    // demand each. Nothing more is taken from iter once demand runs out, or
+    // if a whole chunk wouldn't be accepted, so passing &mut iter lets the
+    // caller carry on from there later.
+    pub fn write_iter<I: IntoIterator<Item = u8>>(&mut self, iter: I, chunk: usize) -> Result<(), ConsumerError> {
+        assert!(chunk > 0, "chunk must be non-zero");
 
-#[cfg(test)]
-mod tests {
+        let mut iter = iter.into_iter();
 
-    use super::*;
-    use std::fs::File;
-    use std::io;
-    use std::io::Cursor;
+        while self.demand > 0 {
+            self.check_writable(chunk, 1)?;
 
-    struct FailWriter {
+            let data: Vec<u8> = iter.by_ref().take(chunk).collect();
+
+            if data.is_empty() {
+                break;
+            }
+
+            self.write_owned(data)?;
+        }
+
+        Ok(())
     }
 
-    impl Write for FailWriter {
-        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
-            Err(io::Error::new(io::ErrorKind::Other, "YOLO"))
+    // Writes several slices as a single unit of demand, using one vectored
+    // write where the writer supports it. Writers that don't just see the
+    // slices one at a time.
+    pub fn write_many(&mut self, bufs: &[&[u8]]) -> Result<usize, ConsumerError> {
+        let total = bufs.iter().map(|buf| buf.len()).sum();
+
+        self.check_writable(total, 1)?;
+        self.catch_up()?;
+
+        // A vectored write can't be held to the rate limit or the io chunk
+        // size, so the slices are joined and written the ordinary way instead.
+        if self.rate_limit.is_some() || self.io_chunk_size.is_some() {
+            return self.write_unit(bufs.concat().into());
         }
 
-        fn flush(&mut self) -> io::Result<()> {
-            Ok(())
+        let mut slices: Vec<IoSlice> = bufs.iter().map(|buf| IoSlice::new(buf)).collect();
+        let mut remaining = &mut slices[..];
+        let mut written = 0;
+
+        while written < total && !self.has_backlog() {
+            match self.timed(|writer| writer.write_vectored(remaining)) {
+                Ok(0) => {
+                    return Err(self.terminate_with(io::ErrorKind::WriteZero.into()));
+                },
+                Ok(n) => {
+                    written += n;
+                    IoSlice::advance_slices(&mut remaining, n);
+                    self.notify_write(n);
+
+                    if let Some(backoff) = self.backoff.as_mut() {
+                        backoff.reset();
+                    }
+                },
+                Err(e) => {
+                    match e.kind() {
+                        io::ErrorKind::Interrupted => {
+                            continue;
+                        },
+                        io::ErrorKind::WouldBlock => {
+                            if let Some(backoff) = self.backoff.as_mut() {
+                                backoff.blocked();
+                            }
+                            break;
+                        },
+                        _ => {
+                            return Err(self.terminate_with(e));
+                        },
+                    }
+                },
+            }
         }
-    }
 
-    struct PartialWriter {
+        let unwritten: Vec<u8> = remaining.iter().flat_map(|slice| slice.iter()).cloned().collect();
+        self.settle(unwritten.into(), written > 0);
+
+        Ok(written)
     }
 
-    impl Write for PartialWriter {
-        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
-            Ok(1)
+    // Reads from reader one chunk per unit of demand and writes what it gets,
+    // stopping at end of file, once demand or room in the buffer runs out, or
+    // when the reader would block. Returns how many bytes were handed over,
+    // buffered ones included.
+    pub fn feed_from<R: Read>(&mut self, reader: &mut R) -> io::Result<u64> {
+        let mut buf = vec![0; self.chunk_size.unwrap_or(producer::CHUNK_SIZE)];
+        let mut total = 0;
+
+        while self.demand > 0 {
+            // Bytes can't be handed back to the reader once they're read, so
+            // only read as many as can be written.
+            self.check_writable(0, 1)?;
+
+            let mut room = buf.len();
+
+            if let Some(cap) = self.capacity {
+                room = room.min(cap.saturating_sub(self.pending_bytes()));
+            }
+
+            if let Some(remaining) = self.limit_remaining() {
+                room = room.min(remaining.min(room as u64) as usize);
+            }
+
+            if room == 0 {
+                break;
+            }
+
+            let n = match reader.read(&mut buf[..room]) {
+                Ok(0) => {
+                    break;
+                },
+                Ok(n) => n,
+                Err(e) => {
+                    match e.kind() {
+                        io::ErrorKind::Interrupted => {
+                            continue;
+                        },
+                        io::ErrorKind::WouldBlock => {
+                            break;
+                        },
+                        _ => {
+                            return Err(e);
+                        },
+                    }
+                },
+            };
+
+            if let Err(err) = self.write(&buf[..n]) {
+                if self.closed {
+                    return Err(err.into());
+                }
+
+                // Shouldn't happen after the checks above, but if the write
+                // is refused anyway the bytes are kept rather than lost.
+                self.settle(buf[..n].to_vec().into(), false);
+            }
+
+            total += n as u64;
         }
 
-        fn flush(&mut self) -> io::Result<()> {
-            Ok(())
+        Ok(total)
+    }
+
+    // Like update(), but stops starting on new buffered units once deadline
+    // has passed, leaving them for a later call. A unit that's already being
+    // written is still given to the writer in full. If the deadline has
+    // already passed, nothing is written at all.
+    pub fn update_with_deadline(&mut self, deadline: Instant) -> UpdateResult {
+        self.update_until(Some(deadline))
+    }
+
+    // Like write(), but if there's no demand yet it keeps calling update()
+    // until there is, giving up with Timeout once the clock reaches deadline.
+    // This busy-waits, so it's only worth it for short deadlines.
+    pub fn write_before(&mut self, data: &[u8], deadline: Instant) -> Result<usize, ConsumerError> {
+        while self.demand == 0 && !self.closed {
+            if self.clock.now() >= deadline {
+                return Err(ConsumerError::Timeout);
+            }
+
+            if self.update() == UpdateResult::Idle {
+                std::hint::spin_loop();
+            }
         }
+
+        self.write(data)
     }
 
+    // Calls f with the number of bytes every time the writer accepts some,
+    // including partial writes.
+    pub fn on_write(&mut self, f: impl FnMut(usize) + 'a) {
+        self.on_write = Some(Box::new(f));
+    }
 
-    #[test]
-    fn write_without_request_fails() {
-        let writer = FailWriter{};
-        let mut consumer = WriteAdapterConsumer::new(writer);
-        assert_eq!(consumer.write(&[65]), Ok(()));
-        assert_eq!(consumer.write(&[65]), Err(ConsumerError::WriteWithoutRequest));
+    // Calls f with the writer's own error whenever a write or flush fails
+    // for good, before it's turned into a ConsumerError.
+    pub fn on_error(&mut self, f: impl FnMut(&io::Error) + 'a) {
+        self.on_error = Some(Box::new(f));
     }
 
-    #[test]
-    fn partial_write() {
-        let writer = PartialWriter{};
-        let mut consumer = WriteAdapterConsumer::new(writer);
-        assert_eq!(consumer.write(&[65, 66]), Ok(()));
-        assert_eq!(consumer.write(&[65]), Err(ConsumerError::WriteWithoutRequest));
+    pub fn demand(&self) -> usize {
+        self.demand
     }
 
-    #[test]
-    fn new_emits_request() {
-        let buf = Cursor::new(vec![0; 15]);
-        let mut consumer = WriteAdapterConsumer::new(buf);
-        let event = consumer.next_event().unwrap();
-        assert_eq!(event, ConsumerEvent::Request(1));
+    // Grants n more units of demand on top of what writes give back.
+    pub fn request(&mut self, n: usize) {
+        let added = self.grant(n);
+        self.emit(ConsumerEvent::Request(added));
+        self.check_accounting();
     }
 
-    #[test]
-    fn buffer() {
-        let buf = Cursor::new(vec![0; 15]);
-        let mut consumer = WriteAdapterConsumer::new(buf);
-        assert_eq!(consumer.write(&[65]), Ok(()));
-        //assert_eq!(consumer.write(&[65]), Ok(()));
+    pub fn set_request_granularity(&mut self, n: usize) {
+        self.request_granularity = n;
     }
 
-    #[test]
-    fn demand_decreases_on_write() {
+    // Total bytes the writer has accepted over the consumer's lifetime.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
 
-        //let mut buf = Cursor::new(vec![0; 15]);
-        //let mut consumer = WriteAdapterConsumer::new(buf);
-        //assert_eq!(consumer.write(&[65]), Ok(()));
-        //assert_eq!(consumer.write(&[65]), Err(ConsumerError::WriteWithoutRequest));
+    // Units written that haven't been given back as demand yet: everything
+    // still buffered, plus completed units whose Request is being held back.
+    pub fn unacked(&self) -> usize {
+        self.buffered.len() + self.withheld + self.batched
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.buffered.iter().chain(self.priority.iter()).map(|unit| unit.len()).sum()
+    }
+
+    // How long to wait before calling update() again, given how many times
+    // in a row the writer has blocked. None if backoff isn't enabled or the
+    // last attempt wasn't blocked.
+    pub fn next_retry_after(&self) -> Option<Duration> {
+        self.backoff.as_ref().and_then(Backoff::delay)
+    }
+
+    // How long the writer's write calls have taken. None unless the consumer
+    // was built with time_writes(), or before the first call.
+    pub fn write_latency_stats(&self) -> Option<LatencyStats> {
+        self.latency.as_ref().and_then(LatencyTimer::stats)
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            demand: self.demand,
+            pending_bytes: self.pending_bytes(),
+            bytes_written: self.bytes_written,
+            queued_events: self.event_queue.len(),
+            closed: self.closed,
+        }
+    }
+
+    // Nothing is buffered, no demand is being held back and nothing is
+    // waiting to be read from the event queue, so there's no point pumping
+    // the consumer until new data arrives.
+    pub fn is_idle(&self) -> bool {
+        !self.has_backlog() && self.batched == 0 && self.withheld == 0 && self.event_queue.is_empty()
+    }
+
+    pub fn peek_event(&self) -> Option<&ConsumerEvent> {
+        self.event_queue.front()
+    }
+
+    // Takes every event currently queued, oldest first.
+    pub fn drain_events(&mut self) -> Drain<'_, ConsumerEvent> {
+        self.event_queue.drain(..)
+    }
+
+    pub fn flush(&mut self) -> Result<(), ConsumerError> {
+        if self.closed {
+            return Err(ConsumerError::Terminated);
+        }
+
+        if let Err(err) = self.writer().flush() {
+            self.notify_error(&err);
+            return Err(ConsumerError::Io(err));
+        }
+
+        self.emit(ConsumerEvent::Flush);
+
+        Ok(())
+    }
+
+    // Keeps writing out the buffer for as long as the writer takes any of it,
+    // then flushes. Unlike finish() the stream stays open afterwards. Fails
+    // with WouldBlock if the writer stops taking data before the buffer is
+    // empty.
+    pub fn flush_all(&mut self) -> Result<(), ConsumerError> {
+        if self.closed {
+            return Err(ConsumerError::Terminated);
+        }
+
+        while self.has_backlog() {
+            let before = self.bytes_written;
+
+            if let Some(err) = self.drain_buffered(None) {
+                return Err(self.terminate_with(err));
+            }
+
+            if self.bytes_written == before && self.has_backlog() {
+                return Err(ConsumerError::Io(io::ErrorKind::WouldBlock.into()));
+            }
+        }
+
+        self.flush()
+    }
+
+    // Borrows the consumer until the returned guard goes out of scope, at
+    // which point flush_all() is called.
+    pub fn drain_guard(&mut self) -> DrainGuard<'_, 'a, W> {
+        DrainGuard::new(self)
+    }
+
+    // Drops anything still buffered and stops accepting writes.
+    pub fn terminate(&mut self, reason: TerminationReason) {
+        if self.closed {
+            return;
+        }
+
+        let unacked = self.unacked();
+        self.buffered.clear();
+        self.priority.clear();
+        self.front_started = false;
+        self.withheld = 0;
+        self.batched = 0;
+        self.above_high_water = false;
+        self.withdraw_demand();
+        self.closed = true;
+        self.emit(ConsumerEvent::Termination { reason, unacked });
+    }
+
+    // The downstream no longer wants data. Anything buffered is dropped and
+    // no more demand will be signalled.
+    pub fn cancel(&mut self) {
+        if !self.closed {
+            self.cancelled = true;
+            self.terminate(TerminationReason::Cancelled);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    // Starts over with a fresh window after a termination or finish. Any
+    // buffered data and queued events are thrown away. The inner writer is
+    // left exactly as it is, so reopening or seeking it is up to the caller.
+    pub fn reset(&mut self, demand: usize) {
+        self.buffered.clear();
+        self.priority.clear();
+        self.front_started = false;
+        self.event_queue.clear();
+        self.withheld = 0;
+        self.batched = 0;
+        self.above_high_water = false;
+        self.demand = 0;
+        #[cfg(debug_assertions)]
+        {
+            self.granted = 0;
+            self.used = 0;
+        }
+        self.closed = false;
+        self.cancelled = false;
+        if let Some(backoff) = self.backoff.as_mut() {
+            backoff.reset();
+        }
+        let added = self.grant(demand);
+        self.emit(ConsumerEvent::Request(added));
+    }
+
+    // Writes out anything still buffered, flushes the writer and signals the
+    // end of the stream. If the buffer can't be drained yet, nothing is
+    // closed and finish can be retried later.
+    pub fn finish(&mut self) -> Result<(), ConsumerError> {
+        if self.closed {
+            return Err(ConsumerError::Terminated);
+        }
+
+        let (_, err) = self.write_buffered(None);
+
+        if let Some(err) = err {
+            return Err(self.terminate_with(err));
+        }
+
+        if self.has_backlog() {
+            return Err(ConsumerError::Io(io::ErrorKind::WouldBlock.into()));
+        }
+
+        if let Err(err) = self.writer().flush() {
+            return Err(self.terminate_with(err));
+        }
+
+        self.closed = true;
+        self.batched = 0;
+        self.withdraw_demand();
+        // Requests are stale once the stream is over, so make sure nothing
+        // acts on one after Finish.
+        self.event_queue.retain(|event| !matches!(event, ConsumerEvent::Request(_)));
+        self.emit(ConsumerEvent::Finish);
+
+        Ok(())
+    }
+
+    // Gives back the writer after a last attempt to write out anything still
+    // buffered and flush it. Like dropping the consumer, this is best effort:
+    // whatever the writer won't take yet is lost.
+    pub fn into_inner(mut self) -> W {
+        if !self.closed {
+            self.write_buffered(None);
+            let _ = self.writer().flush();
+        }
+
+        self.writer.take().expect("writer taken by into_inner")
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().expect("writer taken by into_inner")
+    }
+
+    // Writing through this goes around the demand accounting, and anything
+    // written while data is still buffered lands ahead of it.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.writer()
+    }
+
+    // Swaps in a new writer, e.g. after reconnecting, and hands back the old
+    // one. The old writer gets one last chance to take the buffer and is
+    // flushed if it does. Whatever it won't take, including after an error,
+    // is written to the new writer instead. The old writer's errors don't
+    // end the stream.
+    pub fn replace_writer(&mut self, writer: W) -> W {
+        if self.drain_buffered(None).is_none() {
+            let _ = self.writer().flush();
+        }
+
+        if let Some(backoff) = self.backoff.as_mut() {
+            backoff.reset();
+        }
+
+        mem::replace(self.writer(), writer)
+    }
+}
+
+impl<'a, W: Write + Seek> WriteAdapterConsumer<'a, W> {
+    // Where the next write will land: the writer's own position plus
+    // whatever is still buffered on its way there. Takes &mut self because
+    // asking a Seek for its position does.
+    pub fn position(&mut self) -> io::Result<u64> {
+        let pending = self.pending_bytes() as u64;
+        Ok(self.writer().stream_position()? + pending)
+    }
+
+    // Writes out the buffer and then seeks the writer to pos, so buffered
+    // data still lands where it was meant to. Fails with WouldBlock, without
+    // seeking, if the buffer can't be written out yet. A failed seek doesn't
+    // end the stream.
+    pub fn seek_to(&mut self, pos: u64) -> Result<(), ConsumerError> {
+        self.flush_all()?;
+        self.writer().seek(SeekFrom::Start(pos)).map_err(ConsumerError::Io)?;
+        Ok(())
+    }
+}
+
+impl<'a> BoxedWriteAdapterConsumer<'a> {
+    pub fn builder() -> WriteAdapterConsumerBuilder {
+        WriteAdapterConsumerBuilder::new()
+    }
+}
+
+impl<'a, W: Write> WriteAdapterConsumer<'a, SharedWriter<W>> {
+    // Writes to a writer other threads hold too, locking it for each call.
+    pub fn from_shared(writer: Arc<Mutex<W>>) -> WriteAdapterConsumer<'a, SharedWriter<W>> {
+        WriteAdapterConsumer::new(SharedWriter::new(writer))
+    }
+}
+
+// Each of these is the same as calling new() with the writer boxed.
+impl<'a> From<File> for BoxedWriteAdapterConsumer<'a> {
+    fn from(file: File) -> BoxedWriteAdapterConsumer<'a> {
+        WriteAdapterConsumer::new(Box::new(file))
+    }
+}
+
+impl<'a> From<Stdout> for BoxedWriteAdapterConsumer<'a> {
+    fn from(stdout: Stdout) -> BoxedWriteAdapterConsumer<'a> {
+        WriteAdapterConsumer::new(Box::new(stdout))
+    }
+}
+
+impl<'a> From<Cursor<Vec<u8>>> for BoxedWriteAdapterConsumer<'a> {
+    fn from(cursor: Cursor<Vec<u8>>) -> BoxedWriteAdapterConsumer<'a> {
+        WriteAdapterConsumer::new(Box::new(cursor))
+    }
+}
+
+impl WriteAdapterConsumer<'static, File> {
+    // Creates (or truncates) the file at path and writes to it.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<WriteAdapterConsumer<'static, File>> {
+        Ok(WriteAdapterConsumer::new(File::create(path)?))
+    }
+}
+
+impl<'a, W: Write> WriteAdapterConsumer<'a, W> {
+    fn writer(&mut self) -> &mut W {
+        self.writer.as_mut().expect("writer taken by into_inner")
+    }
+
+    // Makes a call into the writer, timing it if write timing is on.
+    fn timed<T>(&mut self, call: impl FnOnce(&mut W) -> T) -> T {
+        let started = self.latency.as_ref().map(LatencyTimer::start);
+        let result = call(self.writer());
+
+        if let (Some(latency), Some(started)) = (self.latency.as_mut(), started) {
+            latency.stop(started);
+        }
+
+        result
+    }
+
+    // Keep handing the unwritten tail to the writer until it's all gone, the
+    // writer stops accepting bytes or the rate limit is reached. Returns how
+    // many bytes were written, along with the error if the writer failed for
+    // good.
+    fn write_until_blocked(&mut self, data: &[u8]) -> (usize, Option<io::Error>) {
+        let limit = match self.rate_limit.as_mut() {
+            Some(rate_limit) => data.len().min(rate_limit.available()),
+            None => data.len(),
+        };
+        let mut written = 0;
+
+        while written < limit {
+            let end = match self.io_chunk_size {
+                Some(size) => limit.min(written + size),
+                None => limit,
+            };
+
+            match self.timed(|writer| writer.write(&data[written..end])) {
+                Ok(0) => {
+                    // The writer can't take any more, and retrying won't change
+                    // that.
+                    return (written, Some(io::ErrorKind::WriteZero.into()));
+                },
+                Ok(n) => {
+                    written += n;
+                    self.notify_write(n);
+
+                    if let Some(backoff) = self.backoff.as_mut() {
+                        backoff.reset();
+                    }
+                },
+                Err(e) => {
+                    match e.kind() {
+                        io::ErrorKind::Interrupted => {
+                            continue;
+                        },
+                        io::ErrorKind::WouldBlock => {
+                            if let Some(backoff) = self.backoff.as_mut() {
+                                backoff.blocked();
+                            }
+                            break;
+                        },
+                        _ => {
+                            return (written, Some(e));
+                        },
+                    }
+                },
+            }
+        }
+
+        (written, None)
+    }
+
+    // Writes data as a single unit of demand, buffering whatever the writer
+    // doesn't take.
+    fn write_unit(&mut self, mut data: Unit) -> Result<usize, ConsumerError> {
+        let (n, err) = self.write_in_order(&data);
+
+        if let Some(err) = err {
+            return Err(self.terminate_with(err));
+        }
+
+        data.advance(n);
+        self.settle(data, n > 0);
+
+        Ok(n)
+    }
+
+    fn has_backlog(&self) -> bool {
+        !self.buffered.is_empty() || !self.priority.is_empty()
+    }
+
+    // New data mustn't overtake anything still buffered, so while the buffer
+    // isn't empty nothing is written directly.
+    fn write_in_order(&mut self, data: &[u8]) -> (usize, Option<io::Error>) {
+        if !self.has_backlog() {
+            self.write_until_blocked(data)
+        }
+        else {
+            (0, None)
+        }
+    }
+
+    // Gives the buffer a chance to drain before new data is written, handing
+    // back demand for every unit that completes.
+    fn catch_up(&mut self) -> Result<(), ConsumerError> {
+        match self.drain_buffered(None) {
+            Some(err) => Err(self.terminate_with(err)),
+            None => Ok(()),
+        }
+    }
+
+    fn drain_buffered(&mut self, deadline: Option<Instant>) -> Option<io::Error> {
+        if !self.has_backlog() {
+            return None;
+        }
+
+        let (completed, err) = self.write_buffered(deadline);
+        self.withheld += completed;
+
+        // No more demand while still behind, or the upstream would just keep
+        // adding to the buffer.
+        if !self.has_backlog() {
+            for _ in 0..mem::take(&mut self.withheld) {
+                self.regrant();
+            }
+        }
+
+        self.check_watermarks();
+
+        if !self.has_backlog() && err.is_none() {
+            self.emit(ConsumerEvent::Drained);
+        }
+
+        err
+    }
+
+    // Works through the priority data and then the buffered units in order,
+    // stopping at the first one the writer won't fully take, or before
+    // starting one once the deadline has passed. Returns how many buffered
+    // units were completed.
+    fn write_buffered(&mut self, deadline: Option<Instant>) -> (usize, Option<io::Error>) {
+        let mut completed = 0;
+
+        while self.has_backlog() {
+            if deadline.is_some_and(|deadline| self.clock.now() >= deadline) {
+                break;
+            }
+
+            // Priority data goes first, but never into the middle of a unit.
+            let is_priority = !self.priority.is_empty() && !self.front_started;
+            let queue = if is_priority { &mut self.priority } else { &mut self.buffered };
+            let mut unit = queue.pop_front().unwrap();
+            let (n, err) = self.write_until_blocked(&unit);
+
+            if err.is_some() || n != unit.len() {
+                unit.advance(n);
+
+                if !is_priority && n > 0 {
+                    self.front_started = true;
+                }
+
+                let queue = if is_priority { &mut self.priority } else { &mut self.buffered };
+                queue.push_front(unit);
+                return (completed, err);
+            }
+
+            if !is_priority {
+                self.front_started = false;
+                completed += 1;
+            }
+        }
+
+        (completed, None)
+    }
+
+    fn notify_write(&mut self, n: usize) {
+        self.bytes_written += n as u64;
+
+        if let Some(rate_limit) = self.rate_limit.as_mut() {
+            rate_limit.consume(n);
+        }
+
+        if let Some(on_write) = self.on_write.as_mut() {
+            on_write(n);
+        }
+    }
+
+    fn check_writable(&self, len: usize, units: usize) -> Result<(), ConsumerError> {
+        if self.closed {
+            Err(ConsumerError::Terminated)
+        }
+        else if self.limit_remaining().is_some_and(|remaining| len as u64 > remaining) {
+            Err(ConsumerError::LimitReached)
+        }
+        else if self.capacity.is_some_and(|cap| self.pending_bytes() + len > cap) {
+            Err(ConsumerError::BufferFull)
+        }
+        else if self.demand < units {
+            Err(ConsumerError::WriteWithoutRequest)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    // Takes up a unit of demand for whatever the writer didn't accept, or
+    // asks for more right away if it accepted everything. started says
+    // whether the writer took any of the unit.
+    fn settle(&mut self, unwritten: Unit, started: bool) {
+        self.take_demand();
+
+        if unwritten.is_empty() {
+            self.regrant();
+        }
+        else {
+            if self.buffered.is_empty() {
+                self.front_started = started;
+            }
+
+            self.buffered.push_back(unwritten);
+            self.check_watermarks();
+        }
+
+        self.finish_at_limit();
+    }
+
+    fn update_until(&mut self, deadline: Option<Instant>) -> UpdateResult {
+        if self.closed {
+            return UpdateResult::Idle;
+        }
+
+        let before = self.bytes_written;
+
+        if let Some(err) = self.drain_buffered(deadline) {
+            self.emit(ConsumerEvent::Error(err.kind()));
+            self.terminate_with(err);
+        }
+
+        if !self.closed {
+            self.release_batch();
+        }
+
+        self.finish_at_limit();
+        self.check_accounting();
+
+        match (self.bytes_written - before) as usize {
+            0 => {
+                UpdateResult::Idle
+            },
+            bytes => {
+                UpdateResult::Progressed { bytes, more: self.has_backlog() }
+            },
+        }
+    }
+
+    // How many more bytes the byte limit allows, counting what's buffered as
+    // already written.
+    fn limit_remaining(&self) -> Option<u64> {
+        self.byte_limit.map(|max| max.saturating_sub(self.bytes_written + self.pending_bytes() as u64))
+    }
+
+    // Finishes the stream once the byte limit has been reached and written
+    // out. If finishing fails the consumer is terminated, which is all the
+    // caller would need to know.
+    fn finish_at_limit(&mut self) {
+        if !self.closed && self.limit_remaining() == Some(0) && !self.has_backlog() {
+            let _ = self.finish();
+        }
+    }
+
+    fn check_watermarks(&mut self) {
+        let (high, low) = match self.watermarks {
+            Some(watermarks) => watermarks,
+            None => return,
+        };
+        let pending = self.pending_bytes();
+
+        if !self.above_high_water && pending >= high {
+            self.above_high_water = true;
+            self.emit(ConsumerEvent::HighWater);
+        }
+        else if self.above_high_water && pending < low {
+            self.above_high_water = false;
+            self.emit(ConsumerEvent::LowWater);
+        }
+    }
+
+    // Hands out more demand for a unit the writer has finished with, or puts
+    // it towards the next batch if requests are batched.
+    fn regrant(&mut self) {
+        match self.request_batch {
+            Some(batch) => {
+                self.batched += 1;
+
+                if self.batched >= batch {
+                    self.release_batch();
+                }
+            },
+            None => {
+                let added = self.grant(self.request_granularity);
+                self.emit(ConsumerEvent::Request(added));
+            },
+        }
+    }
+
+    fn release_batch(&mut self) {
+        let units = mem::take(&mut self.batched);
+        let added = self.grant(units.saturating_mul(self.request_granularity));
+        self.emit(ConsumerEvent::Request(added));
+    }
+
+    // Adds up to n units of demand, saturating rather than wrapping and
+    // stopping at max_demand. Returns how much was actually added.
+    fn grant(&mut self, n: usize) -> usize {
+        let max = self.max_demand.unwrap_or(usize::MAX);
+        let demand = self.demand.saturating_add(n).min(max).max(self.demand);
+        let added = demand - self.demand;
+        self.demand = demand;
+        #[cfg(debug_assertions)]
+        {
+            self.granted += added as u128;
+        }
+
+        added
+    }
+
+    fn take_demand(&mut self) {
+        debug_assert!(self.demand > 0, "write took demand that was never granted");
+        self.demand -= 1;
+        #[cfg(debug_assertions)]
+        {
+            self.used += 1;
+        }
+    }
+
+    // Takes back whatever demand is left once the stream is over.
+    fn withdraw_demand(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            self.used += self.demand as u128;
+        }
+        self.demand = 0;
+    }
+
+    fn check_accounting(&self) {
+        #[cfg(debug_assertions)]
+        {
+            debug_assert!(self.used <= self.granted, "more demand used than granted");
+            debug_assert_eq!(self.granted - self.used, self.demand as u128, "demand out of step with requests");
+        }
+    }
+
+    fn notify_error(&mut self, err: &io::Error) {
+        if let Some(on_error) = self.on_error.as_mut() {
+            on_error(err);
+        }
+    }
+
+    fn terminate_with(&mut self, err: io::Error) -> ConsumerError {
+        self.notify_error(&err);
+        self.terminate(TerminationReason::WriteError(err.kind()));
+
+        if err.kind() == io::ErrorKind::WriteZero {
+            ConsumerError::WriteZero
+        }
+        else {
+            ConsumerError::Io(err)
+        }
+    }
+}
+
+impl<'a, W: Write> Consumer<[u8]> for WriteAdapterConsumer<'a, W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        WriteAdapterConsumer::write(self, data)
+    }
+
+    // Back-to-back requests are merged if coalescing is on. A full queue
+    // always merges them, since dropping a request would lose demand. A
+    // request for nothing tells the driver nothing, so it's dropped.
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        if event == ConsumerEvent::Request(0) {
+            return true;
+        }
+
+        let full = self.max_events.is_some_and(|max| self.event_queue.len() >= max);
+
+        if let ConsumerEvent::Request(m) = event {
+            let queued = if full {
+                self.event_queue.iter_mut().rev().find(|e| matches!(e, ConsumerEvent::Request(_)))
+            }
+            else if self.coalesce_requests {
+                self.event_queue.back_mut()
+            }
+            else {
+                None
+            };
+
+            if let Some(ConsumerEvent::Request(n)) = queued {
+                *n += m;
+                return true;
+            }
+        }
+
+        if full {
+            if is_droppable(&event) {
+                return false;
+            }
+
+            if let Some(i) = self.event_queue.iter().position(is_droppable) {
+                self.event_queue.remove(i);
+            }
+        }
+
+        self.event_queue.push_back(event);
+        true
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.event_queue.pop_front()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        self.update_until(None)
+    }
+
+    fn request(&mut self, n: usize) {
+        WriteAdapterConsumer::request(self, n);
+    }
+}
+
+// Dropping the consumer makes one last attempt to write out anything still
+// buffered and flush the writer. This is best effort only: errors can't be
+// reported from here so they're ignored, and whatever the writer still won't
+// take is lost. Call finish() to find out whether everything made it.
+// Events that only tell the driver something it can find out again later,
+// so a full queue can do without them.
+fn is_droppable(event: &ConsumerEvent) -> bool {
+    matches!(event, ConsumerEvent::Drained | ConsumerEvent::HighWater | ConsumerEvent::LowWater | ConsumerEvent::Flush)
+}
+
+impl<'a, W: Write> Drop for WriteAdapterConsumer<'a, W> {
+    fn drop(&mut self) {
+        if self.closed || self.writer.is_none() {
+            return;
+        }
+
+        self.write_buffered(None);
+        let _ = self.writer().flush();
+    }
+}
+
+// Data is accepted as long as there is demand for it. Without demand the
+// write fails with WouldBlock, which callers can retry after the next
+// Request. Unlike Consumer::write, an empty write doesn't end the stream.
+impl<'a, W: Write> Write for WriteAdapterConsumer<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        WriteAdapterConsumer::write(self, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        WriteAdapterConsumer::flush(self).map_err(io::Error::from)
+    }
+}
+
+// Copies everything from reader to writer by pumping a ReadAdapterProducer
+// into a WriteAdapterConsumer, so the reader is only pulled from as fast as
+// the writer is able to keep up. Returns the number of bytes copied.
+pub fn pipe<R: Read, W: Write>(reader: R, writer: W) -> io::Result<u64> {
+    let mut producer = ReadAdapterProducer::new(reader);
+    let mut consumer = WriteAdapterConsumer::new(writer);
+    let mut total = 0;
+    let mut ended = false;
+
+    loop {
+        while let Some(event) = consumer.next_event() {
+            match event {
+                ConsumerEvent::Request(n) => {
+                    producer.request(n);
+                },
+                ConsumerEvent::Finish => {
+                    return Ok(total);
+                },
+                ConsumerEvent::Termination { .. } => {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "consumer terminated"));
+                },
+                ConsumerEvent::Flush | ConsumerEvent::Drained | ConsumerEvent::Error(_) |
+                ConsumerEvent::HighWater | ConsumerEvent::LowWater => {
+                },
+            }
+        }
+
+        producer.update();
+
+        while let Some(event) = producer.next_event() {
+            match event {
+                ProducerEvent::Data(data) => {
+                    consumer.write(&data)?;
+                    total += data.len() as u64;
+                },
+                ProducerEvent::End => {
+                    ended = true;
+                },
+                ProducerEvent::Error(kind) => {
+                    return Err(kind.into());
+                },
+            }
+        }
+
+        if ended {
+            match consumer.finish() {
+                Ok(()) => {
+                },
+                Err(ConsumerError::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                },
+                Err(err) => {
+                    return Err(err.into());
+                },
+            }
+        }
+
+        consumer.update();
+    }
+}
+
+// Turns a duplex stream into a producer for its read side and a consumer for
+// its write side. Each half gets its own clone of the stream, so S needs to be
+// a handle where clones share the same underlying connection. For TcpStream
+// that means passing &TcpStream, or a stream from try_clone().
+pub fn split<'a, S: Read + Write + Clone + 'a>(stream: S) -> (ReadAdapterProducer<'a>, WriteAdapterConsumer<'a, S>) {
+    (ReadAdapterProducer::new(stream.clone()), WriteAdapterConsumer::new(stream))
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+    use crate::test_util::{BlockingWriter, MockClock, PartialWriter};
+
+    struct FailWriter {
+    }
+
+    impl Write for FailWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("YOLO"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct FlushCountingWriter {
+        flushes: usize,
+    }
+
+    impl Write for FlushCountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+
+    #[test]
+    fn write_without_request_fails() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.write(&[65]), Err(ConsumerError::WriteWithoutRequest));
+    }
+
+    #[test]
+    fn fatal_error_terminates() {
+        let writer = FailWriter{};
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Err(ConsumerError::Io(io::Error::other("YOLO"))));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Termination { reason: TerminationReason::WriteError(io::ErrorKind::Other), unacked: 0 }));
+        assert_eq!(consumer.write(&[65]), Err(ConsumerError::Terminated));
+    }
+
+    #[test]
+    fn write_zero_terminates() {
+        struct ZeroWriter {
+        }
+
+        impl Write for ZeroWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Ok(0)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut consumer = WriteAdapterConsumer::new(ZeroWriter{});
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Err(ConsumerError::WriteZero));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Termination { reason: TerminationReason::WriteError(io::ErrorKind::WriteZero), unacked: 0 }));
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.write(&[66]), Err(ConsumerError::Terminated));
+    }
+
+    #[test]
+    fn interrupted_write_is_retried() {
+        struct InterruptOnceWriter {
+            interrupted: bool,
+            data: Vec<u8>,
+        }
+
+        impl Write for InterruptOnceWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if !self.interrupted {
+                    self.interrupted = true;
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+
+                self.data.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = InterruptOnceWriter{ interrupted: false, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65, 66]), Ok(2));
+            assert_eq!(consumer.pending_bytes(), 0);
+            assert_eq!(consumer.demand(), 1);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        }
+        assert_eq!(writer.data, vec![65, 66]);
+    }
+
+    #[test]
+    fn update_reports_deferred_write_error() {
+        struct BlockThenFailWriter {
+            blocked: bool,
+        }
+
+        impl Write for BlockThenFailWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                if !self.blocked {
+                    self.blocked = true;
+                    Err(io::ErrorKind::WouldBlock.into())
+                }
+                else {
+                    Err(io::Error::other("YOLO"))
+                }
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut consumer = WriteAdapterConsumer::new(BlockThenFailWriter{ blocked: false });
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Ok(0));
+
+        consumer.update();
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Error(io::ErrorKind::Other)));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Termination { reason: TerminationReason::WriteError(io::ErrorKind::Other), unacked: 1 }));
+    }
+
+    #[test]
+    fn writes_after_finish_or_terminate_are_refused() {
+        let mut finished = WriteAdapterConsumer::new(Vec::new());
+        assert_eq!(finished.finish(), Ok(()));
+        assert_eq!(finished.write(&[65]), Err(ConsumerError::Terminated));
+        assert!(finished.into_inner().is_empty());
+
+        let mut terminated = WriteAdapterConsumer::new(Vec::new());
+        terminated.terminate(TerminationReason::UpstreamError);
+        assert_eq!(terminated.write(&[65]), Err(ConsumerError::Terminated));
+        assert_eq!(terminated.write_many(&[&[65]]), Err(ConsumerError::Terminated));
+        assert!(terminated.into_inner().is_empty());
+    }
+
+    #[test]
+    fn terminate_discards_buffer() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Ok(0));
+
+        consumer.terminate(TerminationReason::Cancelled);
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Termination { reason: TerminationReason::Cancelled, unacked: 1 }));
+        assert_eq!(consumer.write(&[65]), Err(ConsumerError::Terminated));
+
+        consumer.terminate(TerminationReason::UpstreamError);
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn termination_counts_unacked_units() {
+        let writer = BlockingWriter{ blocks: 10, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::builder().demand(3).build(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(3)));
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.write(&[66]), Ok(0));
+        assert_eq!(consumer.unacked(), 2);
+
+        consumer.cancel();
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Termination { reason: TerminationReason::Cancelled, unacked: 2 }));
+        assert_eq!(consumer.unacked(), 0);
+    }
+
+    #[test]
+    fn consumer_errors_convert_to_io_errors() {
+        let kind = |err: ConsumerError| io::Error::from(err).kind();
+        assert_eq!(kind(ConsumerError::WriteWithoutRequest), io::ErrorKind::WouldBlock);
+        assert_eq!(kind(ConsumerError::Terminated), io::ErrorKind::BrokenPipe);
+        assert_eq!(kind(ConsumerError::WriteZero), io::ErrorKind::WriteZero);
+
+        let err = io::Error::new(io::ErrorKind::PermissionDenied, "nope");
+        let converted = io::Error::from(ConsumerError::Io(err));
+        assert_eq!(converted.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(converted.to_string(), "nope");
+
+        fn write_once(data: &[u8]) -> io::Result<usize> {
+            let mut consumer = WriteAdapterConsumer::with_demand(Vec::new(), 0);
+            Ok(consumer.write(data)?)
+        }
+        assert_eq!(write_once(&[65]).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn write_before_times_out_without_demand() {
+        struct FixedClock(Instant);
+
+        impl Clock for FixedClock {
+            fn now(&self) -> Instant {
+                self.0
+            }
+        }
+
+        let now = Instant::now();
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::builder().clock(FixedClock(now)).build(writer);
+        assert_eq!(consumer.write_before(&[65], now), Ok(0));
+        assert_eq!(consumer.write_before(&[66], now), Err(ConsumerError::Timeout));
+        assert_eq!(consumer.pending_bytes(), 1);
+
+        // With time left, it waits for the buffer to drain and then writes.
+        assert_eq!(consumer.write_before(&[66], now + Duration::from_secs(1)), Ok(1));
+        assert_eq!(consumer.get_ref().data, vec![65, 66]);
+    }
+
+    #[test]
+    fn replaced_writer_gets_the_buffer() {
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let old: Box<dyn Write> = Box::new(FailWriter{});
+            let mut consumer = WriteAdapterConsumer::with_initial_buffer(old, vec![65, 66]);
+            assert_eq!(consumer.pending_bytes(), 2);
+
+            consumer.replace_writer(Box::new(&mut cursor));
+            assert_eq!(consumer.pending_bytes(), 2);
+
+            consumer.update();
+            assert_eq!(consumer.pending_bytes(), 0);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[67]), Ok(1));
+        }
+        assert_eq!(cursor.into_inner(), vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn seek_to_overwrites() {
+        let mut consumer = WriteAdapterConsumer::with_demand(Cursor::new(Vec::new()), 3);
+        assert_eq!(consumer.write(&[65, 66]), Ok(2));
+        assert_eq!(consumer.write(&[67]), Ok(1));
+        assert_eq!(consumer.position().unwrap(), 3);
+
+        assert_eq!(consumer.seek_to(0), Ok(()));
+        assert_eq!(consumer.position().unwrap(), 0);
+        assert_eq!(consumer.write(&[68]), Ok(1));
+        assert_eq!(consumer.position().unwrap(), 1);
+
+        assert_eq!(consumer.into_inner().into_inner(), vec![68, 66, 67]);
+    }
+
+    #[test]
+    fn io_error_detail_is_kept() {
+        let mut consumer = WriteAdapterConsumer::new(FailWriter{});
+        match consumer.write(&[65]) {
+            Err(ConsumerError::Io(e)) => assert_eq!(e.to_string(), "YOLO"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn io_errors_compare_by_kind() {
+        assert_eq!(ConsumerError::Io(io::Error::other("a")), ConsumerError::Io(io::Error::other("b")));
+        assert_ne!(ConsumerError::Io(io::Error::other("a")), ConsumerError::Io(io::ErrorKind::WouldBlock.into()));
+        assert_ne!(ConsumerError::Io(io::Error::other("a")), ConsumerError::Terminated);
+    }
+
+    #[test]
+    fn cancel_stops_everything() {
+        let mut writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65]), Ok(0));
+            assert!(!consumer.is_cancelled());
+
+            consumer.cancel();
+            assert!(consumer.is_cancelled());
+            assert_eq!(consumer.demand(), 0);
+            assert_eq!(consumer.pending_bytes(), 0);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Termination { reason: TerminationReason::Cancelled, unacked: 1 }));
+
+            consumer.update();
+            assert_eq!(consumer.next_event(), None);
+            assert_eq!(consumer.write(&[66]), Err(ConsumerError::Terminated));
+        }
+        assert!(writer.data.is_empty());
+    }
+
+    #[test]
+    fn reset_after_termination() {
+        let mut writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.write(&[65]), Ok(0));
+            consumer.terminate(TerminationReason::UpstreamError);
+
+            consumer.reset(2);
+            assert_eq!(consumer.demand(), 2);
+            assert_eq!(consumer.pending_bytes(), 0);
+            assert_eq!(consumer.drain_events().collect::<Vec<_>>(), vec![ConsumerEvent::Request(2)]);
+            assert_eq!(consumer.write(&[66]), Ok(1));
+        }
+        assert_eq!(writer.data, vec![66]);
+    }
+
+    #[test]
+    fn would_block_is_buffered() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.buffered, vec![vec![65]]);
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn request_grants_extra_demand() {
+        let writer = BlockingWriter{ blocks: 3, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+
+        consumer.request(2);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(2)));
+        assert_eq!(consumer.demand(), 3);
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.write(&[66]), Ok(0));
+        assert_eq!(consumer.write(&[67]), Ok(0));
+        assert_eq!(consumer.write(&[68]), Err(ConsumerError::WriteWithoutRequest));
+    }
+
+    #[test]
+    fn demand_saturates() {
+        let mut consumer = WriteAdapterConsumer::builder().coalesce_requests(false).build(Vec::new());
+        consumer.request(usize::MAX);
+        consumer.request(usize::MAX);
+        assert_eq!(consumer.demand(), usize::MAX);
+        assert_eq!(consumer.collect_events(), vec![ConsumerEvent::Request(1), ConsumerEvent::Request(usize::MAX - 1)]);
+
+        // Writing gives the unit straight back, which can't add any more.
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.demand(), usize::MAX);
+    }
+
+    #[test]
+    fn demand_is_clamped_to_max() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(2)
+            .max_demand(4)
+            .coalesce_requests(false)
+            .build(Vec::new());
+        consumer.request(10);
+        assert_eq!(consumer.demand(), 4);
+        assert_eq!(consumer.collect_events(), vec![ConsumerEvent::Request(2), ConsumerEvent::Request(2)]);
+    }
+
+    #[test]
+    fn write_all_through_cursor() {
+        let data: Vec<u8> = (0..100 * 1024).map(|i| i as u8).collect();
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(0)
+            .build(Cursor::new(Vec::new()));
+
+        // Spelled out, since io::Write has a write_all too.
+        assert_eq!(Consumer::write_all(&mut consumer, &data[..60 * 1024]), Ok(()));
+        assert_eq!(Consumer::write_all(&mut consumer, &data[60 * 1024..]), Ok(()));
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.into_inner().into_inner(), data);
+    }
+
+    #[test]
+    fn finish_drops_queued_requests() {
+        let mut consumer = WriteAdapterConsumer::new(Vec::new());
+        consumer.request(2);
+        assert_eq!(consumer.finish(), Ok(()));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Finish));
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn collected_events_compare_in_any_order() {
+        let mut consumer = WriteAdapterConsumer::new(Vec::new());
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.flush(), Ok(()));
+
+        let mut events = consumer.collect_events();
+        events.sort();
+        let mut expected = vec![ConsumerEvent::Flush, ConsumerEvent::Request(2)];
+        expected.sort();
+        assert_eq!(events, expected);
+        assert!(consumer.collect_events().is_empty());
+    }
+
+    #[test]
+    fn stats_after_partial_write() {
+        let mut consumer = WriteAdapterConsumer::new(PartialWriter::default());
+        assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+        assert_eq!(consumer.stats(), Stats {
+            demand: 0,
+            pending_bytes: 2,
+            bytes_written: 1,
+            queued_events: 1,
+            closed: false,
+        });
+    }
+
+    #[test]
+    fn idle_once_events_are_drained() {
+        let mut consumer = WriteAdapterConsumer::new(BlockingWriter{ blocks: 1, data: Vec::new() });
+        assert!(!consumer.is_idle());
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert!(consumer.is_idle());
+
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert!(!consumer.is_idle());
+    }
+
+    #[test]
+    fn zero_requests_are_not_emitted() {
+        let mut consumer = WriteAdapterConsumer::with_demand(Vec::new(), 0);
+        assert_eq!(consumer.next_event(), None);
+
+        consumer.request(0);
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.demand(), 0);
+    }
+
+    #[test]
+    fn requests_follow_granularity() {
+        let mut consumer = WriteAdapterConsumer::with_demand(Vec::new(), 4);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(4)));
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(4)));
+        assert_eq!(consumer.demand(), 7);
+
+        consumer.set_request_granularity(2);
+        assert_eq!(consumer.write(&[66]), Ok(1));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(2)));
+        assert_eq!(consumer.demand(), 8);
+    }
+
+    #[test]
+    fn drained_fires_once_buffer_is_empty() {
+        let writer = BlockingWriter{ blocks: 2, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::builder().demand(2).build(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(2)));
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.write(&[66]), Ok(0));
+
+        consumer.update();
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(2)));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Drained));
+
+        consumer.update();
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn boxed_consumer_erases_writer_type() {
+        let mut consumers: Vec<BoxedWriteAdapterConsumer> = vec![
+            WriteAdapterConsumer::new(Box::new(Vec::new())),
+            WriteAdapterConsumer::new(Box::new(io::sink())),
+        ];
+
+        for consumer in consumers.iter_mut() {
+            assert_eq!(consumer.write(&[65]), Ok(1));
+        }
+    }
+
+    #[test]
+    fn into_inner_returns_writer() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.write(&[65, 66]), Ok(0));
+        let writer = consumer.into_inner();
+        assert_eq!(writer.data, vec![65, 66]);
+    }
+
+    #[test]
+    fn from_cursor() {
+        let mut consumer: WriteAdapterConsumer = Cursor::new(Vec::new()).into();
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+    }
+
+    #[test]
+    fn get_ref_reads_back_writer() {
+        let mut consumer = WriteAdapterConsumer::new(Cursor::new(Vec::new()));
+        assert_eq!(consumer.write(&[65, 66]), Ok(2));
+        assert_eq!(consumer.get_ref().get_ref(), &vec![65, 66]);
+
+        consumer.get_mut().set_position(1);
+        assert_eq!(consumer.get_ref().position(), 1);
+    }
+
+    #[test]
+    fn try_write_reports_outcome() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.try_write(&[65]), Ok(WriteOutcome::Buffered));
+        assert_eq!(consumer.try_write(&[66]), Ok(WriteOutcome::NeedRequest));
+
+        consumer.update();
+        assert_eq!(consumer.try_write(&[67]), Ok(WriteOutcome::Accepted(1)));
+
+        consumer.terminate(TerminationReason::Cancelled);
+        assert_eq!(consumer.try_write(&[68]), Err(ConsumerError::Terminated));
+    }
+
+    #[test]
+    fn try_write_buffered_behind_a_drained_unit() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(2)
+            .build(PartialWriter::default());
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write(&[66]), Ok(0));
+        // Writing out 66 first leaves the writer blocked for the new data.
+        assert_eq!(consumer.try_write(&[67, 68]), Ok(WriteOutcome::Buffered));
+        assert_eq!(consumer.pending_bytes(), 2);
+    }
+
+    #[test]
+    fn new_data_never_overtakes_buffered() {
+        let mut writer = BlockingWriter{ blocks: 2, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::builder().demand(3).build(&mut writer);
+            assert_eq!(consumer.write(&[65, 66]), Ok(0));
+            // Still blocked, so this queues up behind the first write.
+            assert_eq!(consumer.write(&[67]), Ok(0));
+            assert_eq!(consumer.buffered, vec![vec![65, 66], vec![67]]);
+            // Now the buffer drains before the new data goes out.
+            assert_eq!(consumer.write(&[68]), Ok(1));
+            assert_eq!(consumer.pending_bytes(), 0);
+        }
+        assert_eq!(writer.data, vec![65, 66, 67, 68]);
+    }
+
+    #[test]
+    fn feed_from_stops_when_demand_runs_out() {
+        let writer = BlockingWriter{ blocks: 2, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(2)
+            .chunk_size(2)
+            .build(writer);
+        let mut reader = Cursor::new(vec![65, 66, 67, 68, 69]);
+        assert_eq!(consumer.feed_from(&mut reader).unwrap(), 4);
+        assert_eq!(consumer.demand(), 0);
+
+        consumer.update();
+        assert_eq!(consumer.feed_from(&mut reader).unwrap(), 1);
+        assert_eq!(consumer.into_inner().data, vec![65, 66, 67, 68, 69]);
+    }
+
+    #[test]
+    fn feed_from_only_reads_what_fits() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(3)
+            .capacity(4)
+            .build(writer);
+        let mut reader = Cursor::new(vec![65, 66, 67, 68, 69, 70]);
+        assert_eq!(consumer.feed_from(&mut reader).unwrap(), 4);
+        assert_eq!(reader.position(), 4);
+        assert_eq!(consumer.pending_bytes(), 4);
+
+        consumer.update();
+        assert_eq!(consumer.feed_from(&mut reader).unwrap(), 2);
+        assert_eq!(consumer.into_inner().data, vec![65, 66, 67, 68, 69, 70]);
+    }
+
+    #[test]
+    fn feed_from_reads_to_end() {
+        let mut consumer = WriteAdapterConsumer::new(Vec::new());
+        let mut reader = Cursor::new(vec![65; 10000]);
+        assert_eq!(consumer.feed_from(&mut reader).unwrap(), 10000);
+        assert_eq!(consumer.into_inner().len(), 10000);
+    }
+
+    #[test]
+    fn update_with_deadline_stops_once_passed() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use std::time::Duration;
+
+        let start = Instant::now();
+        let elapsed = Rc::new(Cell::new(Duration::ZERO));
+        let clock = MockClock{ start, elapsed: elapsed.clone() };
+        let writer = BlockingWriter{ blocks: 2, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::builder().demand(2).clock(clock).build(writer);
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.write(&[66]), Ok(0));
+
+        elapsed.set(Duration::from_millis(10));
+        consumer.update_with_deadline(start + Duration::from_millis(10));
+        assert_eq!(consumer.pending_bytes(), 2);
+
+        consumer.update_with_deadline(start + Duration::from_millis(11));
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.demand(), 2);
+    }
+
+    #[test]
+    fn write_latency_is_measured() {
+        use std::cell::RefCell;
+
+        // Hands out the given offsets from start, one per reading.
+        struct ScriptedClock {
+            start: Instant,
+            readings: RefCell<VecDeque<u64>>,
+        }
+
+        impl Clock for ScriptedClock {
+            fn now(&self) -> Instant {
+                let millis = self.readings.borrow_mut().pop_front().expect("clock read too often");
+                self.start + Duration::from_millis(millis)
+            }
+        }
+
+        let readings = RefCell::new(VecDeque::from(vec![0, 2, 10, 14, 20, 29]));
+        let clock = ScriptedClock{ start: Instant::now(), readings };
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(3)
+            .clock(clock)
+            .time_writes()
+            .build(Vec::new());
+        assert_eq!(consumer.write_latency_stats(), None);
+
+        for _ in 0..3 {
+            assert_eq!(consumer.write(&[65]), Ok(1));
+        }
+
+        let stats = consumer.write_latency_stats().unwrap();
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.min, Duration::from_millis(2));
+        assert_eq!(stats.max, Duration::from_millis(9));
+        assert_eq!(stats.average(), Duration::from_millis(5));
+    }
+
+    #[test]
+    fn writes_are_untimed_by_default() {
+        let mut consumer = WriteAdapterConsumer::new(Vec::new());
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write_latency_stats(), None);
+    }
+
+    #[test]
+    fn rate_limit_buffers_excess() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use std::time::Duration;
+
+        let elapsed = Rc::new(Cell::new(Duration::ZERO));
+        let clock = MockClock{ start: Instant::now(), elapsed: elapsed.clone() };
+        let mut consumer = WriteAdapterConsumer::builder()
+            .rate_limit(10)
+            .clock(clock)
+            .build(Vec::new());
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+
+        assert_eq!(consumer.write(&[0; 15]), Ok(10));
+        assert_eq!(consumer.pending_bytes(), 5);
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 5);
+
+        elapsed.set(Duration::from_millis(300));
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 2);
+        assert_eq!(consumer.next_event(), None);
+
+        elapsed.set(Duration::from_secs(2));
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.bytes_written(), 15);
+    }
+
+    #[test]
+    fn write_iter_writes_everything() {
+        let mut consumer = WriteAdapterConsumer::new(Cursor::new(Vec::new()));
+        assert_eq!(consumer.write_iter(0u8..=255, 16), Ok(()));
+        assert_eq!(consumer.into_inner().into_inner(), (0u8..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn write_iter_stops_without_demand() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        let mut iter = 0u8..10;
+        assert_eq!(consumer.write_iter(&mut iter, 4), Ok(()));
+        assert_eq!(consumer.pending_bytes(), 4);
+        assert_eq!(iter.next(), Some(4));
+    }
+
+    #[test]
+    fn write_iter_leaves_a_refused_chunk() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(2)
+            .capacity(3)
+            .build(writer);
+        let mut iter = 0u8..10;
+        assert_eq!(consumer.write_iter(&mut iter, 2), Err(ConsumerError::BufferFull));
+        assert_eq!(consumer.pending_bytes(), 2);
+        assert_eq!(iter.next(), Some(2));
+    }
+
+    #[test]
+    fn priority_data_jumps_ahead() {
+        let mut writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.write(&[65, 66]), Ok(0));
+            assert_eq!(consumer.write_priority(&[80]), Ok(()));
+            assert_eq!(consumer.demand(), 0);
+            consumer.update();
+            assert_eq!(consumer.demand(), 1);
+        }
+        assert_eq!(writer.data, vec![80, 65, 66]);
+    }
+
+    #[test]
+    fn priority_data_waits_for_a_started_unit() {
+        let mut consumer = WriteAdapterConsumer::new(PartialWriter::default());
+        assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+        assert_eq!(consumer.write_priority(&[80]), Ok(()));
+        assert_eq!(consumer.pending_bytes(), 3);
+
+        while consumer.pending_bytes() > 0 {
+            consumer.update();
+        }
+
+        assert_eq!(consumer.get_ref().data, vec![65, 66, 67, 80]);
+    }
+
+    #[test]
+    fn buffered_priority_data_drains_first() {
+        let mut writer = BlockingWriter{ blocks: 2, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.write(&[65, 66]), Ok(0));
+            assert_eq!(consumer.write_priority(&[80]), Ok(()));
+            assert_eq!(consumer.pending_bytes(), 3);
+            consumer.update();
+            assert_eq!(consumer.pending_bytes(), 0);
+        }
+        assert_eq!(writer.data, vec![80, 65, 66]);
+    }
+
+    #[test]
+    fn write_owned_buffers_without_copying() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        let data = vec![65; 1024];
+        let ptr = data.as_ptr();
+        assert_eq!(consumer.write_owned(data), Ok(()));
+        assert_eq!(consumer.pending_bytes(), 1024);
+        assert_eq!(consumer.buffered[0].as_ptr(), ptr);
+        assert_eq!(consumer.demand(), 0);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn write_bytes_buffers_a_slice() {
+        let mut consumer = WriteAdapterConsumer::new(PartialWriter::default());
+        let data = bytes::Bytes::from(vec![65, 66, 67]);
+        assert_eq!(consumer.write_bytes(data.clone()), Ok(()));
+
+        match &consumer.buffered[0] {
+            Unit::Shared(buffered) => {
+                assert_eq!(buffered.as_ptr(), data[1..].as_ptr());
+                assert_eq!(buffered, &data.slice(1..));
+            },
+            unit => {
+                panic!("copied into {:?}", unit);
+            },
+        }
+    }
+
+    #[test]
+    fn update_drains_in_as_few_writes_as_possible() {
+        // Blocks once, then takes at most max bytes per call, recording the
+        // length of every buffer it's offered.
+        struct RecordingWriter {
+            blocks: usize,
+            max: usize,
+            calls: Vec<usize>,
+        }
+
+        impl Write for RecordingWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.calls.push(buf.len());
+
+                if self.blocks > 0 {
+                    self.blocks -= 1;
+                    Err(io::Error::new(io::ErrorKind::WouldBlock, "blocked"))
+                }
+                else {
+                    Ok(buf.len().min(self.max))
+                }
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = RecordingWriter{ blocks: 1, max: 4096, calls: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.write(&[0; 10000]), Ok(0));
+            consumer.update();
+            assert_eq!(consumer.pending_bytes(), 0);
+        }
+        assert_eq!(writer.calls, vec![10000, 10000, 5904, 1808]);
+    }
+
+    #[test]
+    fn finish_emits_finish() {
+        let mut writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65, 66]), Ok(0));
+            assert_eq!(consumer.finish(), Ok(()));
+            assert!(consumer.buffered.is_empty());
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Finish));
+            assert_eq!(consumer.write(&[67]), Err(ConsumerError::Terminated));
+            assert_eq!(consumer.finish(), Err(ConsumerError::Terminated));
+        }
+        assert_eq!(writer.data, vec![65, 66]);
+    }
+
+    #[test]
+    fn finish_waits_for_buffer() {
+        let writer = BlockingWriter{ blocks: 2, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.finish(), Err(ConsumerError::Io(io::ErrorKind::WouldBlock.into())));
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.finish(), Ok(()));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Finish));
+    }
+
+    #[test]
+    fn with_capacity_rejects_oversized_writes() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::with_capacity(writer, 4);
+        assert_eq!(consumer.write(&[65, 66, 67, 68, 69]), Err(ConsumerError::BufferFull));
+        assert_eq!(consumer.demand(), 1);
+        assert_eq!(consumer.write(&[65, 66, 67, 68]), Ok(0));
+        assert_eq!(consumer.pending_bytes(), 4);
+    }
+
+    #[test]
+    fn unbounded_by_default() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.write(&[0; 10000]), Ok(0));
+        assert_eq!(consumer.pending_bytes(), 10000);
+    }
+
+    #[test]
+    fn write_many_uses_vectored_write() {
+        #[derive(Default)]
+        struct VectoredWriter {
+            calls: usize,
+            data: Vec<u8>,
+        }
+
+        impl Write for VectoredWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.write_vectored(&[IoSlice::new(buf)])
+            }
+
+            fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+                self.calls += 1;
+                let mut n = 0;
+                for buf in bufs {
+                    self.data.extend_from_slice(buf);
+                    n += buf.len();
+                }
+                Ok(n)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = VectoredWriter::default();
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.write_many(&[&[65], &[66, 67], &[], &[68]]), Ok(4));
+            assert_eq!(consumer.drain_events().collect::<Vec<_>>(), vec![ConsumerEvent::Request(2)]);
+        }
+        assert_eq!(writer.calls, 1);
+        assert_eq!(writer.data, vec![65, 66, 67, 68]);
+    }
+
+    #[test]
+    fn write_many_buffers_tail() {
+        let mut writer = PartialWriter::default();
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.write_many(&[&[65, 66], &[67]]), Ok(1));
+            assert_eq!(consumer.pending_bytes(), 2);
+            assert_eq!(consumer.write_many(&[&[68]]), Err(ConsumerError::WriteWithoutRequest));
+            consumer.update();
+            consumer.update();
+            assert_eq!(consumer.pending_bytes(), 0);
+        }
+        assert_eq!(writer.data, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn builder_defaults_match_new() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumerBuilder::default().build(buf);
+        assert_eq!(consumer.demand(), 1);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[0; 10000]), Ok(10000));
+    }
+
+    #[test]
+    fn builder_sets_demand_and_capacity() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(2)
+            .capacity(2)
+            .build(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(2)));
+        assert_eq!(consumer.write(&[65, 66, 67]), Err(ConsumerError::BufferFull));
+        assert_eq!(consumer.write(&[65, 66]), Ok(0));
+        assert_eq!(consumer.demand(), 1);
+    }
+
+    #[test]
+    fn chunked_write_requests_per_chunk() {
+        let mut output = Vec::new();
+        {
+            let mut consumer = WriteAdapterConsumer::builder()
+                .demand(3)
+                .chunk_size(2)
+                .build(&mut output);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(3)));
+            assert_eq!(consumer.write(&[65, 66, 67, 68, 69, 70, 71]), Err(ConsumerError::WriteWithoutRequest));
+            assert_eq!(consumer.write(&[65, 66, 67, 68, 69]), Ok(5));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(3)));
+            assert_eq!(consumer.demand(), 3);
+        }
+        assert_eq!(output, vec![65, 66, 67, 68, 69]);
+    }
+
+    #[test]
+    fn chunked_write_buffers_remaining_chunks() {
+        let mut writer = PartialWriter::default();
+        {
+            let mut consumer = WriteAdapterConsumer::builder()
+                .demand(3)
+                .chunk_size(2)
+                .build(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(3)));
+            assert_eq!(consumer.write(&[65, 66, 67, 68, 69]), Ok(1));
+            assert_eq!(consumer.buffered, vec![vec![66], vec![67, 68], vec![69]]);
+            assert_eq!(consumer.demand(), 0);
+
+            // The first chunk is done, but its demand waits until the rest
+            // has drained too.
+            consumer.update();
+            assert_eq!(consumer.demand(), 0);
+            assert_eq!(consumer.next_event(), None);
+            assert_eq!(consumer.buffered, vec![vec![67, 68], vec![69]]);
+            assert_eq!(consumer.bytes_written(), 2);
+        }
+        // Dropping the consumer gets one more byte out.
+        assert_eq!(writer.data, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn no_request_while_backlogged() {
+        // Accepts one byte or blocks, as scripted.
+        struct ScriptedWriter {
+            script: VecDeque<bool>,
+        }
+
+        impl Write for ScriptedWriter {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                if self.script.pop_front().unwrap_or(true) {
+                    Ok(1)
+                }
+                else {
+                    Err(io::Error::new(io::ErrorKind::WouldBlock, "blocked"))
+                }
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let script = vec![true, false, false, true, false].into();
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(2)
+            .build(ScriptedWriter{ script });
+        consumer.drain_events();
+
+        assert_eq!(consumer.write(&[65, 66]), Ok(1));
+        assert_eq!(consumer.write(&[67]), Ok(0));
+
+        // 66 finishes the first write, but 67 is still waiting.
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 1);
+        assert_eq!(consumer.next_event(), None);
+
+        consumer.update();
+        assert_eq!(consumer.collect_events(), vec![
+            ConsumerEvent::Request(2),
+            ConsumerEvent::Drained,
+        ]);
+        assert_eq!(consumer.demand(), 2);
+    }
+
+    #[test]
+    fn on_write_reports_accepted_bytes() {
+        let mut counts = Vec::new();
+        {
+            let mut consumer = WriteAdapterConsumer::new(PartialWriter::default());
+            consumer.on_write(|n| counts.push(n));
+            assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+            consumer.update();
+            consumer.update();
+            assert_eq!(consumer.write_many(&[&[68]]), Ok(0));
+            consumer.update();
+        }
+        assert_eq!(counts, vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn on_error_sees_writer_error() {
+        let mut messages = Vec::new();
+        {
+            let mut consumer = WriteAdapterConsumer::new(FailWriter{});
+            consumer.on_error(|err| messages.push(err.to_string()));
+            assert!(consumer.write(&[65]).is_err());
+        }
+        assert_eq!(messages, vec!["YOLO".to_string()]);
+    }
+
+    #[test]
+    fn flush_flushes_writer() {
+        let mut writer = FlushCountingWriter::default();
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.flush(), Ok(()));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Flush));
+            assert_eq!(consumer.flush(), Ok(()));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Flush));
+            consumer.finish().unwrap();
+        }
+        assert_eq!(writer.flushes, 3);
+    }
+
+    #[test]
+    fn flush_error_is_returned() {
+        struct FailFlushWriter {
+        }
+
+        impl Write for FailFlushWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Err(io::Error::other("YOLO"))
+            }
+        }
+
+        let mut consumer = WriteAdapterConsumer::new(FailFlushWriter{});
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.flush(), Err(ConsumerError::Io(io::Error::other("YOLO"))));
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn empty_write_finishes() {
+        let mut writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65]), Ok(0));
+            assert_eq!(consumer.write(&[]), Ok(0));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Finish));
+            assert_eq!(consumer.write(&[66]), Err(ConsumerError::Terminated));
+        }
+        assert_eq!(writer.data, vec![65]);
+    }
+
+    #[test]
+    fn empty_io_write_does_not_finish() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::new(buf);
+        assert_eq!(Write::write(&mut consumer, &[]).unwrap(), 0);
+        assert_eq!(consumer.drain_events().collect::<Vec<_>>(), vec![ConsumerEvent::Request(1)]);
+    }
+
+    #[test]
+    fn drop_writes_buffered_and_flushes() {
+        let mut writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.write(&[65, 66]), Ok(0));
+        }
+        assert_eq!(writer.data, vec![65, 66]);
+
+        let mut writer = FlushCountingWriter::default();
+        {
+            WriteAdapterConsumer::new(&mut writer);
+        }
+        assert_eq!(writer.flushes, 1);
+    }
+
+    #[test]
+    fn drop_ignores_errors() {
+        let mut consumer = WriteAdapterConsumer::new(BlockingWriter{ blocks: 2, data: Vec::new() });
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        drop(consumer);
+    }
+
+    #[test]
+    fn partial_write() {
+        let writer = PartialWriter::default();
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65, 66]), Ok(1));
+
+        // The unit isn't done until the rest of it is out, so its demand
+        // stays spent until then.
+        assert_eq!(consumer.pending_bytes(), 1);
+        assert_eq!(consumer.demand(), 0);
+        assert_eq!(consumer.write(&[67]), Err(ConsumerError::WriteWithoutRequest));
+
+        while consumer.pending_bytes() > 0 {
+            consumer.update();
+        }
+
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.demand(), 1);
+        assert!(consumer.write(&[67]).is_ok());
+
+        while consumer.pending_bytes() > 0 {
+            consumer.update();
+        }
+
+        assert_eq!(consumer.get_ref().data, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn one_request_per_completed_write() {
+        let mut consumer = WriteAdapterConsumer::new(PartialWriter::default());
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+
+        let mut requests = 0;
+
+        for _ in 0..5 {
+            assert!(consumer.write(&[65, 66, 67]).is_ok());
+
+            while consumer.pending_bytes() > 0 {
+                consumer.update();
+            }
+
+            for event in consumer.collect_events() {
+                if let ConsumerEvent::Request(n) = event {
+                    requests += n;
+                }
+            }
+        }
+
+        assert_eq!(requests, 5);
+        assert_eq!(consumer.demand(), 1);
+    }
+
+    #[test]
+    fn partial_write_completes_after_updates() {
+        let mut writer = PartialWriter::default();
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65, 66, 67, 68]), Ok(1));
+            assert_eq!(consumer.buffered, vec![vec![66, 67, 68]]);
+
+            for _ in 0..3 {
+                assert_eq!(consumer.next_event(), None);
+                consumer.update();
+            }
+
+            assert!(consumer.buffered.is_empty());
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        }
+        assert_eq!(writer.data, vec![65, 66, 67, 68]);
+    }
+
+    #[test]
+    fn initial_buffer_drains_before_any_demand() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::with_initial_buffer(writer, vec![65, 66]);
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.demand(), 0);
+        assert_eq!(consumer.pending_bytes(), 2);
+        assert_eq!(consumer.write(&[67]), Err(ConsumerError::WriteWithoutRequest));
+
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 2);
+        assert_eq!(consumer.next_event(), None);
+
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+
+        assert_eq!(consumer.write(&[67]), Ok(1));
+        assert_eq!(consumer.get_ref().data, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn requests_are_batched() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(4)
+            .request_batch(4)
+            .build(Vec::new());
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(4)));
+
+        for _ in 0..3 {
+            assert_eq!(consumer.write(&[65]), Ok(1));
+            assert_eq!(consumer.next_event(), None);
+        }
+
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(4)));
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.demand(), 4);
+    }
+
+    #[test]
+    fn update_releases_a_partial_batch() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(4)
+            .request_batch(4)
+            .build(Vec::new());
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(4)));
+
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write(&[66]), Ok(1));
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.demand(), 2);
+
+        consumer.update();
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(2)));
+        assert_eq!(consumer.demand(), 4);
+    }
+
+    #[test]
+    fn new_emits_request() {
+        let buf = Cursor::new(vec![0; 15]);
+        let mut consumer = WriteAdapterConsumer::new(buf);
+        let event = consumer.next_event().unwrap();
+        assert_eq!(event, ConsumerEvent::Request(1));
+    }
+
+    #[test]
+    fn with_demand_emits_single_request() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::with_demand(buf, 3);
+        assert_eq!(consumer.demand(), 3);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(3)));
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn with_zero_demand_still_regrants() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::with_demand(buf, 0);
+        assert_eq!(consumer.next_event(), None);
+        consumer.request(1);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.demand(), 1);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+    }
+
+    #[test]
+    fn peek_event_does_not_consume() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::new(buf);
+        assert_eq!(consumer.peek_event(), Some(&ConsumerEvent::Request(1)));
+        assert_eq!(consumer.peek_event(), Some(&ConsumerEvent::Request(1)));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.peek_event(), None);
+    }
+
+    #[test]
+    fn drain_events_takes_everything() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::new(buf);
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.flush(), Ok(()));
+
+        let events: Vec<ConsumerEvent> = consumer.drain_events().collect();
+        assert_eq!(events, vec![
+            ConsumerEvent::Request(2),
+            ConsumerEvent::Flush,
+        ]);
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn requests_can_be_left_separate() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::builder().coalesce_requests(false).build(buf);
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write(&[66]), Ok(1));
+        assert_eq!(consumer.collect_events(), vec![
+            ConsumerEvent::Request(1),
+            ConsumerEvent::Request(1),
+            ConsumerEvent::Request(1),
+        ]);
+    }
+
+    #[test]
+    fn consecutive_requests_are_coalesced() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::new(buf);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write(&[66]), Ok(1));
+        assert_eq!(consumer.write(&[67]), Ok(1));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(3)));
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn bounded_event_queue() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::builder()
+            .max_events(2)
+            .build(buf);
+        assert_eq!(consumer.flush(), Ok(()));
+        assert!(!consumer.emit(ConsumerEvent::Drained));
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.event_queue.len(), 2);
+        assert_eq!(consumer.drain_events().collect::<Vec<_>>(), vec![
+            ConsumerEvent::Request(2),
+            ConsumerEvent::Flush,
+        ]);
+        assert!(consumer.emit(ConsumerEvent::Finish));
+    }
+
+    #[test]
+    fn full_queue_makes_room_for_a_request() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::builder()
+            .max_events(1)
+            .build(buf);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.flush(), Ok(()));
+        assert_eq!(consumer.write(&[1]), Ok(1));
+        assert_eq!(consumer.collect_events(), vec![ConsumerEvent::Request(1)]);
+        assert_eq!(consumer.demand(), 1);
+    }
+
+    #[test]
+    fn full_queue_still_takes_termination() {
+        let buf = Cursor::new(Vec::new());
+        let mut consumer = WriteAdapterConsumer::builder()
+            .max_events(1)
+            .build(buf);
+        consumer.cancel();
+        assert_eq!(consumer.collect_events(), vec![
+            ConsumerEvent::Request(1),
+            ConsumerEvent::Termination { reason: TerminationReason::Cancelled, unacked: 0 },
+        ]);
+    }
+
+    #[test]
+    fn buffer() {
+        let buf = Cursor::new(vec![0; 15]);
+        let mut consumer = WriteAdapterConsumer::new(buf);
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        //assert_eq!(consumer.write(&[65]), Ok(()));
+    }
+
+    #[test]
+    fn update_flushes_buffered() {
+        let mut writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65, 66]), Ok(0));
+            assert_eq!(consumer.next_event(), None);
+
+            consumer.update();
+            assert!(consumer.buffered.is_empty());
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[67]), Ok(1));
+        }
+        assert_eq!(writer.data, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn backoff_grows_while_blocked() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .backoff(Duration::from_millis(10), Duration::from_secs(1))
+            .build(BlockingWriter{ blocks: 3, data: Vec::new() });
+        assert_eq!(consumer.next_retry_after(), None);
+
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.next_retry_after(), Some(Duration::from_millis(10)));
+        consumer.update();
+        assert_eq!(consumer.next_retry_after(), Some(Duration::from_millis(20)));
+        consumer.update();
+        assert_eq!(consumer.next_retry_after(), Some(Duration::from_millis(40)));
+
+        consumer.update();
+        assert_eq!(consumer.next_retry_after(), None);
+        assert_eq!(consumer.into_inner().data, vec![65]);
+    }
+
+    #[test]
+    fn backoff_covers_vectored_writes() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .backoff(Duration::from_millis(10), Duration::from_secs(1))
+            .build(BlockingWriter{ blocks: 1, data: Vec::new() });
+        assert_eq!(consumer.write_many(&[&[65], &[66]]), Ok(0));
+        assert_eq!(consumer.next_retry_after(), Some(Duration::from_millis(10)));
+
+        consumer.update();
+        assert_eq!(consumer.next_retry_after(), None);
+        assert_eq!(consumer.write_many(&[&[67], &[68]]), Ok(2));
+        assert_eq!(consumer.next_retry_after(), None);
+    }
+
+    #[test]
+    fn accounting_holds_for_a_correct_driver() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .chunk_size(2)
+            .demand(3)
+            .build(PartialWriter{ blocked: false, data: Vec::new() });
+        let mut data = vec![65, 66, 67, 68, 69];
+
+        while !data.is_empty() || !consumer.is_idle() {
+            if !data.is_empty() && consumer.demand() >= 3 {
+                assert!(consumer.write(&data).is_ok());
+                data.clear();
+            }
+            consumer.update();
+            consumer.drain_events();
+        }
+        consumer.request(2);
+        assert_eq!(consumer.finish(), Ok(()));
+        consumer.reset(1);
+        assert!(consumer.write(&[70]).is_ok());
+        assert_eq!(consumer.into_inner().data, vec![65, 66, 67, 68, 69, 70]);
+    }
+
+    #[test]
+    fn flush_all_empties_buffer() {
+        let mut consumer = WriteAdapterConsumer::new(PartialWriter{ blocked: false, data: Vec::new() });
+        assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+        assert_eq!(consumer.pending_bytes(), 2);
+
+        assert_eq!(consumer.flush_all(), Ok(()));
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert!(consumer.write(&[68]).is_ok());
+        assert_eq!(consumer.into_inner().data, vec![65, 66, 67, 68]);
+    }
+
+    #[test]
+    fn flush_all_gives_up_when_blocked() {
+        let mut consumer = WriteAdapterConsumer::new(BlockingWriter{ blocks: 2, data: Vec::new() });
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert!(matches!(consumer.flush_all(), Err(ConsumerError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock));
+        assert_eq!(consumer.pending_bytes(), 1);
+    }
+
+    #[test]
+    fn watermarks_follow_capacity() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .capacity(8)
+            .demand(3)
+            .build(BlockingWriter{ blocks: 2, data: Vec::new() });
+        consumer.drain_events();
+
+        assert_eq!(consumer.write(&[65; 5]), Ok(0));
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.write(&[66]), Ok(0));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::HighWater));
+
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.collect_events(), vec![
+            ConsumerEvent::Request(2),
+            ConsumerEvent::LowWater,
+            ConsumerEvent::Drained,
+        ]);
+    }
+
+    #[test]
+    fn watermarks_fire_once_per_crossing() {
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(4)
+            .watermarks(2, 1)
+            .build(PartialWriter{ blocked: false, data: Vec::new() });
+        consumer.drain_events();
+
+        // 65 goes through, 66 and 67 are left over.
+        assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::HighWater));
+        // This catches up on 66 first, so 67 and 68 are left.
+        assert_eq!(consumer.write(&[68]), Ok(0));
+        assert_eq!(consumer.pending_bytes(), 2);
+        assert_eq!(consumer.next_event(), None);
+
+        // One byte per update. 1 pending isn't below the low watermark yet.
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 1);
+        assert!(!consumer.collect_events().contains(&ConsumerEvent::LowWater));
+
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert!(consumer.collect_events().contains(&ConsumerEvent::LowWater));
+    }
+
+    #[test]
+    fn byte_limit_exact_boundary() {
+        let mut consumer = WriteAdapterConsumer::with_byte_limit(Vec::new(), 3);
+        consumer.drain_events();
+
+        assert_eq!(consumer.write(&[65, 66]), Ok(2));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[67, 68]), Err(ConsumerError::LimitReached));
+        assert_eq!(consumer.bytes_written(), 2);
+
+        assert_eq!(consumer.write(&[67]), Ok(1));
+        assert_eq!(consumer.collect_events(), vec![ConsumerEvent::Finish]);
+        // Once the limit has finished the stream it's closed like any other.
+        assert_eq!(consumer.write(&[68]), Err(ConsumerError::Terminated));
+        assert_eq!(consumer.into_inner(), vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn byte_limit_finishes_once_drained() {
+        let mut consumer = WriteAdapterConsumer::with_byte_limit(BlockingWriter{ blocks: 1, data: Vec::new() }, 2);
+        consumer.drain_events();
+
+        assert_eq!(consumer.write(&[65, 66]), Ok(0));
+        assert_eq!(consumer.next_event(), None);
+
+        consumer.update();
+        assert!(consumer.collect_events().contains(&ConsumerEvent::Finish));
+        assert_eq!(consumer.into_inner().data, vec![65, 66]);
+    }
+
+    #[test]
+    fn io_chunk_size_bounds_each_call() {
+        // Takes at most 4 KiB per call, and blocks on every other call.
+        struct SlowWriter {
+            blocked: bool,
+            largest_call: usize,
+            data: Vec<u8>,
+        }
+
+        impl Write for SlowWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.largest_call = self.largest_call.max(buf.len());
+                self.blocked = !self.blocked;
+                if !self.blocked {
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "blocked"));
+                }
+
+                let n = buf.len().min(4096);
+                self.data.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let input = vec![65; 1024 * 1024];
+        let mut consumer = WriteAdapterConsumer::builder()
+            .io_chunk_size(16 * 1024)
+            .build(SlowWriter{ blocked: false, largest_call: 0, data: Vec::new() });
+        assert_eq!(consumer.write(&input), Ok(4096));
+        assert!(consumer.pending_bytes() < input.len());
+
+        let mut updates = 0;
+        while consumer.pending_bytes() > 0 {
+            consumer.update();
+            updates += 1;
+        }
+        assert!(updates > 1);
+
+        let writer = consumer.into_inner();
+        assert_eq!(writer.largest_call, 16 * 1024);
+        assert_eq!(writer.data, input);
+    }
+
+    #[test]
+    fn update_reports_idle() {
+        let mut consumer = WriteAdapterConsumer::new(BlockingWriter{ blocks: 2, data: Vec::new() });
+        assert_eq!(consumer.update(), UpdateResult::Idle);
+
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.update(), UpdateResult::Idle);
+    }
+
+    #[test]
+    fn update_reports_progress() {
+        let mut consumer = WriteAdapterConsumer::new(PartialWriter{ blocked: false, data: Vec::new() });
+        assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+        assert_eq!(consumer.update(), UpdateResult::Progressed { bytes: 1, more: true });
+        assert_eq!(consumer.update(), UpdateResult::Progressed { bytes: 1, more: false });
+        assert_eq!(consumer.update(), UpdateResult::Idle);
+    }
+
+    #[test]
+    fn update_keeps_buffer_on_would_block() {
+        let writer = BlockingWriter{ blocks: 2, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65, 66]), Ok(0));
+
+        consumer.update();
+        assert_eq!(consumer.buffered, vec![vec![65, 66]]);
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.write(&[67]), Err(ConsumerError::WriteWithoutRequest));
+    }
+
+    #[test]
+    fn demand_decreases_on_write() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.demand(), 1);
+        assert_eq!(consumer.write(&[65]), Ok(0));
+        assert_eq!(consumer.demand(), 0);
+    }
+
+    #[test]
+    fn demand_restored_after_buffer_drains() {
+        let mut writer = PartialWriter::default();
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut writer);
+            assert_eq!(consumer.demand(), 1);
+            assert_eq!(consumer.write(&[65, 66, 67, 68]), Ok(1));
+            assert_eq!(consumer.demand(), 0);
+
+            for _ in 0..3 {
+                consumer.update();
+            }
+
+            assert_eq!(consumer.pending_bytes(), 0);
+            assert_eq!(consumer.demand(), 1);
+        }
+        assert_eq!(writer.data, vec![65, 66, 67, 68]);
+    }
+
+    #[test]
+    fn bytes_written_counts_flushed_bytes() {
+        let mut consumer = WriteAdapterConsumer::new(PartialWriter::default());
+        assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+        assert_eq!(consumer.bytes_written(), 1);
+        consumer.update();
+        consumer.update();
+        assert_eq!(consumer.bytes_written(), 3);
+
+        let mut consumer = WriteAdapterConsumer::new(Vec::new());
+        for _ in 0..10 {
+            assert_eq!(consumer.write(&[0; 100]), Ok(100));
+        }
+        assert_eq!(consumer.bytes_written(), 1000);
+    }
+
+    #[test]
+    fn pending_bytes_tracks_buffer() {
+        let writer = PartialWriter::default();
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.write(&[65, 66, 67]), Ok(1));
+        assert_eq!(consumer.pending_bytes(), 2);
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 1);
+        consumer.update();
+        assert_eq!(consumer.pending_bytes(), 0);
+    }
+
+    #[test]
+    fn consumer_over_custom_item() {
+        #[derive(Debug, PartialEq)]
+        struct Frame {
+            id: u8,
+        }
+
+        #[derive(Default)]
+        struct FrameConsumer {
+            frames: Vec<u8>,
+            event_queue: VecDeque<ConsumerEvent>,
+        }
+
+        impl Consumer<Frame> for FrameConsumer {
+            fn write(&mut self, data: &Frame) -> Result<usize, ConsumerError> {
+                self.frames.push(data.id);
+                self.emit(ConsumerEvent::Request(1));
+                Ok(1)
+            }
+
+            fn emit(&mut self, event: ConsumerEvent) -> bool {
+                self.event_queue.push_back(event);
+                true
+            }
+
+            fn next_event(&mut self) -> Option<ConsumerEvent> {
+                self.event_queue.pop_front()
+            }
+
+            fn update(&mut self) -> UpdateResult {
+                UpdateResult::Idle
+            }
+        }
+
+        let mut consumer = FrameConsumer::default();
+        assert_eq!(consumer.write(&Frame{ id: 1 }), Ok(1));
+        assert_eq!(consumer.write(&Frame{ id: 2 }), Ok(1));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.frames, vec![1, 2]);
+    }
+
+    #[test]
+    fn write_str_writes_utf8() {
+        let mut consumer = WriteAdapterConsumer::new(Cursor::new(Vec::new()));
+        assert_eq!(consumer.write_str("héllo"), Ok(()));
+        assert_eq!(consumer.into_inner().into_inner(), "héllo".as_bytes());
+    }
+
+    #[test]
+    fn io_write_impl() {
+        let mut output = Vec::new();
+        {
+            let mut consumer = WriteAdapterConsumer::new(&mut output);
+            write!(consumer, "{}-{}", 65, 66).unwrap();
+            Write::flush(&mut consumer).unwrap();
+        }
+        assert_eq!(output, b"65-66");
+    }
+
+    #[test]
+    fn io_write_without_demand_would_block() {
+        let writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        let mut consumer = WriteAdapterConsumer::new(writer);
+        assert_eq!(Write::write(&mut consumer, &[65, 66]).unwrap(), 2);
+        let err = Write::write(&mut consumer, &[67]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn split_shares_the_stream() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // Whatever is written can be read back.
+        #[derive(Clone, Default)]
+        struct Loopback {
+            buf: Rc<RefCell<Cursor<Vec<u8>>>>,
+        }
+
+        impl Read for Loopback {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.buf.borrow_mut().read(buf)
+            }
+        }
+
+        impl Write for Loopback {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let mut cursor = self.buf.borrow_mut();
+                let pos = cursor.position();
+                cursor.get_mut().extend_from_slice(buf);
+                cursor.set_position(pos);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (mut producer, mut consumer) = split(Loopback::default());
+        assert_eq!(consumer.write(&[65, 66]), Ok(2));
+
+        producer.request(1);
+        producer.update();
+        assert_eq!(producer.next_event(), Some(ProducerEvent::Data(vec![65, 66])));
+    }
+
+    #[test]
+    fn pipe_copies_everything() {
+        let input: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+        let mut output = Vec::new();
+        assert_eq!(pipe(Cursor::new(input.clone()), &mut output).unwrap(), 10000);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn pipe_respects_backpressure() {
+        let mut writer = PartialWriter::default();
+        assert_eq!(pipe(Cursor::new(vec![65, 66, 67]), &mut writer).unwrap(), 3);
+        assert_eq!(writer.data, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn pipe_empty_reader() {
+        let mut output = Vec::new();
+        assert_eq!(pipe(io::empty(), &mut output).unwrap(), 0);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn pipe_fatal_write_error() {
+        let result = pipe(Cursor::new(vec![65]), FailWriter{});
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Other);
     }
 
     #[test]
     fn it_works() {
         let num_lines = 10;
         let mut num_written = 0;
-        let mut file = File::create("test.txt").unwrap();
-        let mut consumer = WriteAdapterConsumer::new(file);
+        let mut consumer = WriteAdapterConsumer::from_path("test.txt").unwrap();
 
         while num_written < num_lines {
             match consumer.next_event() {
 
                 Some(event) => {
-                    println!("{:?}", event);
                     match event {
-                        ConsumerEvent::Request(n) => {
-                            println!("Requested: {}", n);
-                            assert_eq!(consumer.write(&[65, 67, 65, 67, 10]), Ok(()));
+                        ConsumerEvent::Request(_) => {
+                            assert_eq!(consumer.write(&[65, 67, 65, 67, 10]), Ok(5));
                             num_written += 1;
                         },
                         _ => {
@@ -191,7 +3586,6 @@ mod tests {
                     }
                 },
                 None => {
-                    println!("no more events");
                     break;
                 },
             }
@@ -0,0 +1,162 @@
+use std::mem;
+use std::io::{self, Write};
+
+use crate::{BoxedWriteAdapterConsumer, Consumer, ConsumerError, ConsumerEvent, UpdateResult, WriteAdapterConsumer};
+
+
+// Only ever hands complete, newline-terminated lines to the writer. Anything
+// after the last newline is held back until more data arrives, or until
+// finish() writes it out as is.
+pub struct LineConsumer<'a> {
+    inner: BoxedWriteAdapterConsumer<'a>,
+    partial: Vec<u8>,
+}
+
+impl<'a> LineConsumer<'a> {
+    pub fn new<T: 'a + Write>(writer: T) -> LineConsumer<'a> {
+        LineConsumer {
+            inner: WriteAdapterConsumer::new(Box::new(writer)),
+            partial: Vec::new(),
+        }
+    }
+
+    pub fn demand(&self) -> usize {
+        self.inner.demand()
+    }
+
+    // Bytes held back waiting for the end of their line.
+    pub fn partial_len(&self) -> usize {
+        self.partial.len()
+    }
+
+    // The trailing partial line goes out without needing any demand, but
+    // only once everything buffered ahead of it has, so that it can't jump
+    // the queue. Until then this fails with WouldBlock and can be retried.
+    pub fn finish(&mut self) -> Result<(), ConsumerError> {
+        if !self.partial.is_empty() {
+            self.inner.update();
+
+            if self.inner.pending_bytes() > 0 {
+                return Err(ConsumerError::Io(io::ErrorKind::WouldBlock.into()));
+            }
+
+            self.inner.write_priority(&self.partial)?;
+            self.partial.clear();
+        }
+
+        self.inner.finish()
+    }
+}
+
+impl<'a> Consumer<[u8]> for LineConsumer<'a> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        if data.is_empty() {
+            return self.finish().map(|_| 0);
+        }
+
+        self.inner.check_writable(self.partial.len() + data.len(), 1)?;
+
+        match data.iter().rposition(|&b| b == b'\n') {
+            Some(i) => {
+                let mut lines = mem::replace(&mut self.partial, data[i + 1..].to_vec());
+                lines.extend_from_slice(&data[..=i]);
+
+                match self.inner.write(&lines) {
+                    Ok(n) => Ok(n),
+                    Err(err) => {
+                        lines.truncate(lines.len() - (i + 1));
+                        self.partial = lines;
+                        Err(err)
+                    },
+                }
+            },
+            None => {
+                // Nothing goes to the writer, so the demand is handed straight
+                // back.
+                self.partial.extend_from_slice(data);
+                self.emit(ConsumerEvent::Request(1));
+                Ok(0)
+            },
+        }
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        self.inner.emit(event)
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.inner.next_event()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        self.inner.update()
+    }
+
+    fn request(&mut self, n: usize) {
+        self.inner.request(n);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::iter;
+    use crate::test_util::BlockingWriter;
+
+    #[test]
+    fn only_complete_lines_are_written() {
+        let mut output = Vec::new();
+        {
+            let mut consumer = LineConsumer::new(&mut output);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(b"ab"), Ok(0));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(b"c\nd"), Ok(4));
+            assert_eq!(consumer.partial_len(), 1);
+            assert_eq!(consumer.write(b"e\nf\ng"), Ok(5));
+            assert_eq!(consumer.partial_len(), 1);
+        }
+        assert_eq!(output, b"abc\nde\nf\n");
+    }
+
+    #[test]
+    fn finish_writes_trailing_partial_line() {
+        let mut output = Vec::new();
+        {
+            let mut consumer = LineConsumer::new(&mut output);
+            assert_eq!(consumer.write(b"a\nb"), Ok(2));
+            assert_eq!(consumer.finish(), Ok(()));
+            assert_eq!(consumer.partial_len(), 0);
+            assert_eq!(consumer.write(b"c"), Err(ConsumerError::Terminated));
+        }
+        assert_eq!(output, b"a\nb");
+    }
+
+    #[test]
+    fn finish_needs_no_demand() {
+        let mut writer = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = LineConsumer::new(&mut writer);
+            assert_eq!(consumer.write(b"a\nb"), Ok(0));
+            assert_eq!(consumer.demand(), 0);
+            assert_eq!(consumer.write(b""), Ok(0));
+            assert_eq!(consumer.collect_events().last(), Some(&ConsumerEvent::Finish));
+        }
+        assert_eq!(writer.data, b"a\nb");
+    }
+
+    #[test]
+    fn empty_write_finishes() {
+        let mut output = Vec::new();
+        {
+            let mut consumer = LineConsumer::new(&mut output);
+            assert_eq!(consumer.write(b"abc"), Ok(0));
+            assert_eq!(consumer.write(b""), Ok(0));
+            let events: Vec<ConsumerEvent> = iter::from_fn(|| consumer.next_event()).collect();
+            assert_eq!(events.last(), Some(&ConsumerEvent::Finish));
+        }
+        assert_eq!(output, b"abc");
+    }
+}
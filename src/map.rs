@@ -0,0 +1,127 @@
+use std::io::Write;
+
+use crate::{Consumer, ConsumerError, ConsumerEvent, UpdateResult, WriteAdapterConsumer};
+
+
+// Runs every chunk through f before handing it to the inner consumer. The
+// byte counts returned by write are for the transformed data.
+//
+// The capacity and byte limit checks depend on the transformed length, so
+// they can only run after f, and a chunk they refuse goes through f again
+// when it's retried. f should therefore be a pure function of its input.
+pub struct MapConsumer<'a, F: FnMut(&[u8]) -> Vec<u8>, W: Write = Box<dyn Write + 'a>> {
+    inner: WriteAdapterConsumer<'a, W>,
+    f: F,
+}
+
+impl<'a, F: FnMut(&[u8]) -> Vec<u8>, W: Write> MapConsumer<'a, F, W> {
+    pub fn new(inner: WriteAdapterConsumer<'a, W>, f: F) -> MapConsumer<'a, F, W> {
+        MapConsumer {
+            inner,
+            f,
+        }
+    }
+
+    pub fn demand(&self) -> usize {
+        self.inner.demand()
+    }
+}
+
+impl<'a, F: FnMut(&[u8]) -> Vec<u8>, W: Write> Consumer<[u8]> for MapConsumer<'a, F, W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        if data.is_empty() {
+            return self.inner.write(data);
+        }
+
+        // The checks that don't need the output run before f does.
+        self.inner.check_writable(0, 1)?;
+
+        let mapped = (self.f)(data);
+
+        // An empty write would end the stream, so if f swallowed everything
+        // the demand goes straight back instead.
+        if mapped.is_empty() {
+            self.emit(ConsumerEvent::Request(1));
+            return Ok(0);
+        }
+
+        self.inner.write(&mapped)
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        self.inner.emit(event)
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.inner.next_event()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        self.inner.update()
+    }
+
+    fn request(&mut self, n: usize) {
+        self.inner.request(n);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::test_util::BlockingWriter;
+
+    #[test]
+    fn maps_before_writing() {
+        let mut output = Vec::new();
+        {
+            let inner = WriteAdapterConsumer::new(&mut output);
+            let mut consumer = MapConsumer::new(inner, |data: &[u8]| data.to_ascii_uppercase());
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(b"abc"), Ok(3));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.demand(), 1);
+        }
+        assert_eq!(output, b"ABC");
+    }
+
+    #[test]
+    fn empty_result_keeps_stream_open() {
+        let mut output = Vec::new();
+        {
+            let inner = WriteAdapterConsumer::new(&mut output);
+            let mut consumer = MapConsumer::new(inner, |_: &[u8]| Vec::new());
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(b"abc"), Ok(0));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(b""), Ok(0));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Finish));
+        }
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn refused_write_is_mapped_again() {
+        use std::cell::Cell;
+
+        let calls = Cell::new(0);
+        let inner = WriteAdapterConsumer::builder()
+            .demand(2)
+            .capacity(3)
+            .build(BlockingWriter{ blocks: 1, data: Vec::new() });
+        let mut consumer = MapConsumer::new(inner, |data: &[u8]| {
+            calls.set(calls.get() + 1);
+            data.to_ascii_uppercase()
+        });
+
+        assert_eq!(consumer.write(b"ab"), Ok(0));
+        assert_eq!(consumer.write(b"cd"), Err(ConsumerError::BufferFull));
+        assert_eq!(calls.get(), 2);
+
+        consumer.update();
+        assert_eq!(consumer.write(b"cd"), Ok(2));
+        assert_eq!(calls.get(), 3);
+        assert_eq!(consumer.inner.get_ref().data, b"ABCD");
+    }
+}
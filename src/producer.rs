@@ -0,0 +1,145 @@
+use std::io::{self, Read};
+use std::collections::VecDeque;
+
+
+pub(crate) const CHUNK_SIZE: usize = 4096;
+
+#[derive(Debug, PartialEq)]
+pub enum ProducerEvent {
+    Data(Vec<u8>),
+    End,
+    Error(io::ErrorKind),
+}
+
+pub trait Producer {
+    fn request(&mut self, n: usize);
+    fn emit(&mut self, event: ProducerEvent);
+    fn next_event(&mut self) -> Option<ProducerEvent>;
+    fn update(&mut self);
+}
+
+
+pub struct ReadAdapterProducer<'a> {
+    reader: Box<dyn Read + 'a>,
+    demand: usize,
+    event_queue: VecDeque<ProducerEvent>,
+    ended: bool,
+}
+
+impl<'a> ReadAdapterProducer<'a> {
+    pub fn new<T: 'a + Read>(reader: T) -> ReadAdapterProducer<'a> {
+        ReadAdapterProducer {
+            reader: Box::new(reader),
+            demand: 0,
+            event_queue: VecDeque::new(),
+            ended: false,
+        }
+    }
+}
+
+impl<'a> Producer for ReadAdapterProducer<'a> {
+    fn request(&mut self, n: usize) {
+        self.demand += n;
+    }
+
+    fn emit(&mut self, event: ProducerEvent) {
+        self.event_queue.push_back(event);
+    }
+
+    fn next_event(&mut self) -> Option<ProducerEvent> {
+        self.event_queue.pop_front()
+    }
+
+    // Each unit of demand is answered with at most one chunk of data.
+    fn update(&mut self) {
+        let mut buf = [0; CHUNK_SIZE];
+
+        while self.demand > 0 && !self.ended {
+            match self.reader.read(&mut buf) {
+                Ok(0) => {
+                    self.ended = true;
+                    self.emit(ProducerEvent::End);
+                },
+                Ok(n) => {
+                    self.demand -= 1;
+                    self.emit(ProducerEvent::Data(buf[..n].into()));
+                },
+                Err(e) => {
+                    match e.kind() {
+                        io::ErrorKind::Interrupted => {
+                            continue;
+                        },
+                        io::ErrorKind::WouldBlock => {
+                            break;
+                        },
+                        kind => {
+                            self.ended = true;
+                            self.emit(ProducerEvent::Error(kind));
+                        },
+                    }
+                },
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+
+    struct FailReader {
+    }
+
+    impl Read for FailReader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("YOLO"))
+        }
+    }
+
+    #[test]
+    fn no_data_without_request() {
+        let mut producer = ReadAdapterProducer::new(Cursor::new(vec![65, 66]));
+        producer.update();
+        assert_eq!(producer.next_event(), None);
+    }
+
+    #[test]
+    fn request_emits_data_then_end() {
+        let mut producer = ReadAdapterProducer::new(Cursor::new(vec![65, 66]));
+        producer.request(1);
+        producer.update();
+        assert_eq!(producer.next_event(), Some(ProducerEvent::Data(vec![65, 66])));
+        assert_eq!(producer.next_event(), None);
+
+        producer.request(1);
+        producer.update();
+        assert_eq!(producer.next_event(), Some(ProducerEvent::End));
+        assert_eq!(producer.next_event(), None);
+    }
+
+    #[test]
+    fn data_is_chunked_per_request() {
+        let mut producer = ReadAdapterProducer::new(Cursor::new(vec![0; CHUNK_SIZE + 1]));
+        producer.request(1);
+        producer.update();
+        assert_eq!(producer.next_event(), Some(ProducerEvent::Data(vec![0; CHUNK_SIZE])));
+        assert_eq!(producer.next_event(), None);
+
+        producer.request(2);
+        producer.update();
+        assert_eq!(producer.next_event(), Some(ProducerEvent::Data(vec![0])));
+        assert_eq!(producer.next_event(), Some(ProducerEvent::End));
+    }
+
+    #[test]
+    fn read_error_emits_error() {
+        let mut producer = ReadAdapterProducer::new(FailReader{});
+        producer.request(1);
+        producer.update();
+        assert_eq!(producer.next_event(), Some(ProducerEvent::Error(io::ErrorKind::Other)));
+        assert_eq!(producer.next_event(), None);
+    }
+}
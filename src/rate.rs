@@ -0,0 +1,69 @@
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::Clock;
+
+
+// A token bucket holding up to a second's worth of bytes. It starts full and
+// refills continuously as the clock moves on.
+pub(crate) struct RateLimiter {
+    bytes_per_sec: u64,
+    tokens: f64,
+    last: Instant,
+    clock: Rc<dyn Clock>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(bytes_per_sec: u64, clock: Rc<dyn Clock>) -> RateLimiter {
+        RateLimiter {
+            bytes_per_sec,
+            tokens: bytes_per_sec as f64,
+            last: clock.now(),
+            clock,
+        }
+    }
+
+    // How many bytes may be written right now.
+    pub(crate) fn available(&mut self) -> usize {
+        let now = self.clock.now();
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        let capacity = self.bytes_per_sec as f64;
+
+        self.tokens = (self.tokens + elapsed * capacity).min(capacity);
+        self.last = now;
+
+        self.tokens as usize
+    }
+
+    pub(crate) fn consume(&mut self, n: usize) {
+        self.tokens = (self.tokens - n as f64).max(0.0);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use crate::test_util::MockClock;
+
+    #[test]
+    fn refills_over_time_up_to_capacity() {
+        let elapsed = Rc::new(Cell::new(Duration::ZERO));
+        let clock = MockClock{ start: Instant::now(), elapsed: elapsed.clone() };
+        let mut limiter = RateLimiter::new(100, Rc::new(clock));
+        assert_eq!(limiter.available(), 100);
+
+        limiter.consume(100);
+        assert_eq!(limiter.available(), 0);
+
+        elapsed.set(Duration::from_millis(250));
+        assert_eq!(limiter.available(), 25);
+
+        elapsed.set(Duration::from_secs(10));
+        assert_eq!(limiter.available(), 100);
+    }
+}
@@ -0,0 +1,205 @@
+use std::io::{self, Read};
+use std::collections::VecDeque;
+
+use crate::{Consumer, ConsumerError, ConsumerEvent, UpdateResult};
+
+
+// The other way round from WriteAdapterConsumer: bytes written to it are
+// kept in a buffer of up to capacity bytes until something reads them out.
+// There's demand for one more write whenever the buffer isn't full, and a
+// write has to fit in the space that's left.
+pub struct ReadableConsumer {
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    demand: usize,
+    event_queue: VecDeque<ConsumerEvent>,
+    coalesce_requests: bool,
+    closed: bool,
+}
+
+impl ReadableConsumer {
+    pub fn new(capacity: usize) -> ReadableConsumer {
+        assert!(capacity > 0, "capacity must be non-zero");
+
+        let mut consumer = ReadableConsumer {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            demand: 1,
+            event_queue: VecDeque::new(),
+            coalesce_requests: true,
+            closed: false,
+        };
+
+        consumer.emit(ConsumerEvent::Request(1));
+
+        consumer
+    }
+
+    pub fn demand(&self) -> usize {
+        self.demand
+    }
+
+    // Same as the coalesce_requests builder option on WriteAdapterConsumer,
+    // and on by default like there.
+    pub fn coalesce_requests(mut self, coalesce: bool) -> ReadableConsumer {
+        self.coalesce_requests = coalesce;
+        self
+    }
+
+    // Bytes waiting to be read.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+impl Consumer<[u8]> for ReadableConsumer {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        if self.closed {
+            Err(ConsumerError::Terminated)
+        }
+        else if data.is_empty() {
+            // Finishing works even with a full buffer, and drops any Request
+            // still queued since it's stale after Finish.
+            self.closed = true;
+            self.demand = 0;
+            self.event_queue.retain(|event| !matches!(event, ConsumerEvent::Request(_)));
+            self.emit(ConsumerEvent::Finish);
+            Ok(0)
+        }
+        else if self.demand == 0 || self.buffer.len() + data.len() > self.capacity {
+            Err(ConsumerError::WriteWithoutRequest)
+        }
+        else {
+            self.buffer.extend(data);
+
+            if self.buffer.len() < self.capacity {
+                self.emit(ConsumerEvent::Request(1));
+            }
+            else {
+                self.demand = 0;
+            }
+
+            Ok(data.len())
+        }
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        if event == ConsumerEvent::Request(0) {
+            return true;
+        }
+
+        if let ConsumerEvent::Request(m) = event {
+            let coalesce = self.coalesce_requests;
+
+            if let Some(ConsumerEvent::Request(n)) = self.event_queue.back_mut().filter(|_| coalesce) {
+                *n += m;
+                return true;
+            }
+        }
+
+        self.event_queue.push_back(event);
+        true
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.event_queue.pop_front()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        UpdateResult::Idle
+    }
+}
+
+// Reads block with WouldBlock while the buffer is empty, and report the end
+// of the stream once it's been finished and everything has been read.
+impl Read for ReadableConsumer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            if self.closed || buf.is_empty() {
+                return Ok(0);
+            }
+
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let n = self.buffer.len().min(buf.len());
+
+        for (dst, src) in buf.iter_mut().zip(self.buffer.drain(..n)) {
+            *dst = src;
+        }
+
+        if n > 0 && self.demand == 0 && !self.closed {
+            self.demand = 1;
+            self.emit(ConsumerEvent::Request(1));
+        }
+
+        Ok(n)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn reads_back_in_chunks() {
+        let mut consumer = ReadableConsumer::new(4);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65, 66, 67]), Ok(3));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+
+        // Doesn't fit in the one byte that's left.
+        assert_eq!(consumer.write(&[68, 69]), Err(ConsumerError::WriteWithoutRequest));
+        assert_eq!(consumer.write(&[68]), Ok(1));
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.write(&[69]), Err(ConsumerError::WriteWithoutRequest));
+
+        let mut buf = [0; 1];
+        assert_eq!(consumer.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf, [65]);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+
+        let mut buf = [0; 8];
+        assert_eq!(consumer.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], &[66, 67, 68]);
+        assert_eq!(consumer.read(&mut buf).unwrap_err().kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn finish_reads_as_end_of_file() {
+        let mut consumer = ReadableConsumer::new(4);
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write(&[]), Ok(0));
+
+        let mut output = Vec::new();
+        assert_eq!(consumer.read_to_end(&mut output).unwrap(), 1);
+        assert_eq!(output, vec![65]);
+    }
+
+    #[test]
+    fn requests_can_be_left_separate() {
+        let mut consumer = ReadableConsumer::new(4).coalesce_requests(false);
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write(&[66]), Ok(1));
+        assert_eq!(consumer.collect_events(), vec![
+            ConsumerEvent::Request(1),
+            ConsumerEvent::Request(1),
+            ConsumerEvent::Request(1),
+        ]);
+    }
+
+    #[test]
+    fn full_buffer_can_still_finish() {
+        let mut consumer = ReadableConsumer::new(2);
+        assert_eq!(consumer.write(&[65, 66]), Ok(2));
+        assert_eq!(consumer.demand(), 0);
+        assert_eq!(consumer.write(&[]), Ok(0));
+        assert_eq!(consumer.collect_events(), vec![ConsumerEvent::Finish]);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{BoxedWriteAdapterConsumer, Consumer, ConsumerEvent};
+
+
+// Drives a set of consumers fairly. Each tick updates every consumer that
+// has something to do and takes at most one event from each, starting one
+// further along every time so nobody is always served first.
+pub struct Scheduler<'a> {
+    consumers: Vec<(usize, BoxedWriteAdapterConsumer<'a>)>,
+    next_id: usize,
+    start: usize,
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new() -> Scheduler<'a> {
+        Scheduler {
+            consumers: Vec::new(),
+            next_id: 0,
+            start: 0,
+        }
+    }
+
+    // Adds a consumer and returns the id its events are tagged with.
+    pub fn add(&mut self, consumer: BoxedWriteAdapterConsumer<'a>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.consumers.push((id, consumer));
+
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<BoxedWriteAdapterConsumer<'a>> {
+        let index = self.consumers.iter().position(|(i, _)| *i == id)?;
+        Some(self.consumers.remove(index).1)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut BoxedWriteAdapterConsumer<'a>> {
+        self.consumers.iter_mut().find(|(i, _)| *i == id).map(|(_, consumer)| consumer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.consumers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.consumers.is_empty()
+    }
+
+    // Returns the events collected this tick, in the order they were taken.
+    pub fn tick(&mut self) -> Vec<(usize, ConsumerEvent)> {
+        let len = self.consumers.len();
+        let mut events = Vec::new();
+
+        for i in 0..len {
+            let (id, consumer) = &mut self.consumers[(self.start + i) % len];
+
+            if consumer.is_idle() {
+                continue;
+            }
+
+            consumer.update();
+
+            if let Some(event) = consumer.next_event() {
+                events.push((*id, event));
+            }
+        }
+
+        if len > 0 {
+            self.start = (self.start + 1) % len;
+        }
+
+        events
+    }
+}
+
+impl<'a> Default for Scheduler<'a> {
+    fn default() -> Scheduler<'a> {
+        Scheduler::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Write;
+    use crate::WriteAdapterConsumer;
+    use crate::test_util::BlockingWriter;
+
+    #[test]
+    fn round_robin_without_starving() {
+        let mut scheduler = Scheduler::new();
+
+        // a has data stuck in its buffer, b just has a lot of events queued.
+        let mut a = WriteAdapterConsumer::new(Box::new(BlockingWriter{ blocks: 2, data: Vec::new() }) as Box<dyn Write>);
+        assert_eq!(a.write(&[65]), Ok(0));
+        let a = scheduler.add(a);
+
+        let mut b = WriteAdapterConsumer::builder()
+            .coalesce_requests(false)
+            .build(Box::new(Vec::new()) as Box<dyn Write>);
+        for _ in 0..4 {
+            b.request(1);
+        }
+        let b = scheduler.add(b);
+
+        assert_eq!(scheduler.tick(), vec![(a, ConsumerEvent::Request(1)), (b, ConsumerEvent::Request(1))]);
+        assert_eq!(scheduler.tick(), vec![(b, ConsumerEvent::Request(1)), (a, ConsumerEvent::Request(1))]);
+        assert_eq!(scheduler.tick(), vec![(a, ConsumerEvent::Drained), (b, ConsumerEvent::Request(1))]);
+
+        // a has nothing left to do, so only b is served.
+        assert_eq!(scheduler.tick(), vec![(b, ConsumerEvent::Request(1))]);
+        assert_eq!(scheduler.get_mut(a).map(|consumer| consumer.pending_bytes()), Some(0));
+        assert!(scheduler.remove(a).is_some());
+        assert_eq!(scheduler.len(), 1);
+    }
+
+    #[test]
+    fn partial_batch_is_released() {
+        let mut scheduler = Scheduler::new();
+
+        let mut consumer = WriteAdapterConsumer::builder()
+            .demand(2)
+            .request_batch(4)
+            .build(Box::new(Vec::new()) as Box<dyn Write>);
+        consumer.drain_events();
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.next_event(), None);
+        assert!(!consumer.is_idle());
+        let id = scheduler.add(consumer);
+
+        assert_eq!(scheduler.tick(), vec![(id, ConsumerEvent::Request(1))]);
+        assert_eq!(scheduler.tick(), vec![]);
+    }
+}
@@ -0,0 +1,89 @@
+// io::ErrorKind has no serde support of its own, so kinds go over the wire
+// by name. Kinds without a name here, including any added to std later,
+// come out the other side as Other.
+pub(crate) mod error_kind {
+    use std::io::ErrorKind;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const KINDS: &[(ErrorKind, &str)] = &[
+        (ErrorKind::NotFound, "NotFound"),
+        (ErrorKind::PermissionDenied, "PermissionDenied"),
+        (ErrorKind::ConnectionRefused, "ConnectionRefused"),
+        (ErrorKind::ConnectionReset, "ConnectionReset"),
+        (ErrorKind::ConnectionAborted, "ConnectionAborted"),
+        (ErrorKind::NotConnected, "NotConnected"),
+        (ErrorKind::AddrInUse, "AddrInUse"),
+        (ErrorKind::AddrNotAvailable, "AddrNotAvailable"),
+        (ErrorKind::BrokenPipe, "BrokenPipe"),
+        (ErrorKind::AlreadyExists, "AlreadyExists"),
+        (ErrorKind::WouldBlock, "WouldBlock"),
+        (ErrorKind::InvalidInput, "InvalidInput"),
+        (ErrorKind::InvalidData, "InvalidData"),
+        (ErrorKind::TimedOut, "TimedOut"),
+        (ErrorKind::WriteZero, "WriteZero"),
+        (ErrorKind::Interrupted, "Interrupted"),
+        (ErrorKind::Unsupported, "Unsupported"),
+        (ErrorKind::UnexpectedEof, "UnexpectedEof"),
+        (ErrorKind::OutOfMemory, "OutOfMemory"),
+        (ErrorKind::Other, "Other"),
+    ];
+
+    pub(crate) fn serialize<S: Serializer>(kind: &ErrorKind, serializer: S) -> Result<S::Ok, S::Error> {
+        let name = KINDS.iter()
+            .find(|(k, _)| k == kind)
+            .map_or("Other", |(_, name)| name);
+
+        serializer.serialize_str(name)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ErrorKind, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        Ok(KINDS.iter()
+            .find(|(_, n)| *n == name)
+            .map_or(ErrorKind::Other, |(kind, _)| *kind))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use std::io;
+
+    use crate::{ConsumerEvent, TerminationReason};
+
+    fn round_trip(event: ConsumerEvent) -> String {
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(serde_json::from_str::<ConsumerEvent>(&json).unwrap(), event);
+        json
+    }
+
+    #[test]
+    fn events_round_trip() {
+        assert_eq!(round_trip(ConsumerEvent::Request(3)), r#"{"type":"Request","value":3}"#);
+        assert_eq!(round_trip(ConsumerEvent::Finish), r#"{"type":"Finish"}"#);
+        assert_eq!(
+            round_trip(ConsumerEvent::Termination { reason: TerminationReason::Cancelled, unacked: 0 }),
+            r#"{"type":"Termination","value":{"reason":{"type":"Cancelled"},"unacked":0}}"#,
+        );
+        assert_eq!(
+            round_trip(ConsumerEvent::Termination { reason: TerminationReason::WriteError(io::ErrorKind::BrokenPipe), unacked: 2 }),
+            r#"{"type":"Termination","value":{"reason":{"type":"WriteError","value":"BrokenPipe"},"unacked":2}}"#,
+        );
+
+        round_trip(ConsumerEvent::Termination { reason: TerminationReason::UpstreamError, unacked: 0 });
+        round_trip(ConsumerEvent::Flush);
+        round_trip(ConsumerEvent::Drained);
+        round_trip(ConsumerEvent::Error(io::ErrorKind::WouldBlock));
+        round_trip(ConsumerEvent::HighWater);
+        round_trip(ConsumerEvent::LowWater);
+    }
+
+    #[test]
+    fn unknown_error_kind_becomes_other() {
+        let event: ConsumerEvent = serde_json::from_str(r#"{"type":"Error","value":"Flooded"}"#).unwrap();
+        assert_eq!(event, ConsumerEvent::Error(io::ErrorKind::Other));
+    }
+}
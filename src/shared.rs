@@ -0,0 +1,100 @@
+use std::io::{self, IoSlice, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+
+// A writer shared with other threads. The lock is only held for the length
+// of each call, so several consumers can take turns writing to it. A
+// poisoned lock shows up as an io error, which ends the stream like any
+// other write error.
+pub struct SharedWriter<W: Write> {
+    inner: Arc<Mutex<W>>,
+}
+
+impl<W: Write> SharedWriter<W> {
+    pub fn new(inner: Arc<Mutex<W>>) -> SharedWriter<W> {
+        SharedWriter {
+            inner,
+        }
+    }
+
+    pub fn get_ref(&self) -> &Arc<Mutex<W>> {
+        &self.inner
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<'_, W>> {
+        self.inner.lock().map_err(|_| io::Error::other("shared writer lock poisoned"))
+    }
+}
+
+impl<W: Write> Clone for SharedWriter<W> {
+    fn clone(&self) -> SharedWriter<W> {
+        SharedWriter::new(self.inner.clone())
+    }
+}
+
+impl<W: Write> Write for SharedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock()?.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        self.lock()?.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock()?.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Cursor;
+    use std::thread;
+
+    use crate::{ConsumerError, WriteAdapterConsumer};
+
+    #[test]
+    fn consumers_share_a_writer() {
+        let shared = Arc::new(Mutex::new(Cursor::new(Vec::new())));
+
+        let threads: Vec<_> = [b'a', b'b'].iter().map(|&byte| {
+            let shared = shared.clone();
+
+            thread::spawn(move || {
+                let mut consumer = WriteAdapterConsumer::from_shared(shared);
+
+                for _ in 0..100 {
+                    assert_eq!(consumer.write(&[byte; 10]), Ok(10));
+                }
+
+                assert_eq!(consumer.finish(), Ok(()));
+            })
+        }).collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let data = shared.lock().unwrap().get_ref().clone();
+        assert_eq!(data.len(), 2000);
+        assert_eq!(data.iter().filter(|byte| **byte == b'a').count(), 1000);
+        assert_eq!(data.iter().filter(|byte| **byte == b'b').count(), 1000);
+    }
+
+    #[test]
+    fn poisoned_lock_is_an_io_error() {
+        let shared = Arc::new(Mutex::new(Vec::<u8>::new()));
+
+        let poisoner = shared.clone();
+        let _ = thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("poisoning the lock");
+        }).join();
+
+        let mut consumer = WriteAdapterConsumer::from_shared(shared);
+        assert_eq!(consumer.write(&[65]), Err(ConsumerError::Io(io::Error::other("shared writer lock poisoned"))));
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use crate::{Consumer, ConsumerError, ConsumerEvent, UpdateResult};
+
+
+// Throws away everything written to it while following the same demand
+// protocol as WriteAdapterConsumer, which makes it useful for measuring the
+// cost of the protocol on its own.
+pub struct SinkConsumer {
+    demand: usize,
+    event_queue: VecDeque<ConsumerEvent>,
+    bytes_discarded: u64,
+    coalesce_requests: bool,
+    closed: bool,
+}
+
+impl SinkConsumer {
+    pub fn new() -> SinkConsumer {
+        SinkConsumer::with_demand(1)
+    }
+
+    pub fn with_demand(initial_demand: usize) -> SinkConsumer {
+        let mut consumer = SinkConsumer {
+            demand: initial_demand,
+            event_queue: VecDeque::new(),
+            bytes_discarded: 0,
+            coalesce_requests: true,
+            closed: false,
+        };
+
+        consumer.emit(ConsumerEvent::Request(initial_demand));
+
+        consumer
+    }
+
+    pub fn demand(&self) -> usize {
+        self.demand
+    }
+
+    pub fn bytes_discarded(&self) -> u64 {
+        self.bytes_discarded
+    }
+
+    // Same as the coalesce_requests builder option on WriteAdapterConsumer,
+    // and on by default like there.
+    pub fn coalesce_requests(mut self, coalesce: bool) -> SinkConsumer {
+        self.coalesce_requests = coalesce;
+        self
+    }
+}
+
+impl Default for SinkConsumer {
+    fn default() -> SinkConsumer {
+        SinkConsumer::new()
+    }
+}
+
+impl Consumer<[u8]> for SinkConsumer {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        if self.closed {
+            Err(ConsumerError::Terminated)
+        }
+        else if data.is_empty() {
+            // Same as WriteAdapterConsumer::finish(): ending the stream needs
+            // no demand, and any Request still queued is stale after Finish.
+            self.closed = true;
+            self.demand = 0;
+            self.event_queue.retain(|event| !matches!(event, ConsumerEvent::Request(_)));
+            self.emit(ConsumerEvent::Finish);
+            Ok(0)
+        }
+        else if self.demand == 0 {
+            Err(ConsumerError::WriteWithoutRequest)
+        }
+        else {
+            self.bytes_discarded += data.len() as u64;
+            self.emit(ConsumerEvent::Request(1));
+            Ok(data.len())
+        }
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        if event == ConsumerEvent::Request(0) {
+            return true;
+        }
+
+        if let ConsumerEvent::Request(m) = event {
+            let coalesce = self.coalesce_requests;
+
+            if let Some(ConsumerEvent::Request(n)) = self.event_queue.back_mut().filter(|_| coalesce) {
+                *n += m;
+                return true;
+            }
+        }
+
+        self.event_queue.push_back(event);
+        true
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.event_queue.pop_front()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        UpdateResult::Idle
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn discards_and_requests() {
+        let mut consumer = SinkConsumer::new();
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[65, 66]), Ok(2));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.write(&[67]), Ok(1));
+        assert_eq!(consumer.bytes_discarded(), 3);
+        assert_eq!(consumer.demand(), 1);
+    }
+
+    #[test]
+    fn requests_can_be_left_separate() {
+        let mut consumer = SinkConsumer::new().coalesce_requests(false);
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write(&[66]), Ok(1));
+        assert_eq!(consumer.collect_events(), vec![
+            ConsumerEvent::Request(1),
+            ConsumerEvent::Request(1),
+            ConsumerEvent::Request(1),
+        ]);
+    }
+
+    #[test]
+    fn with_demand_window() {
+        let mut consumer = SinkConsumer::with_demand(4);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(4)));
+        assert_eq!(consumer.demand(), 4);
+    }
+
+    #[test]
+    fn empty_write_finishes() {
+        let mut consumer = SinkConsumer::with_demand(0);
+        assert_eq!(consumer.write(&[]), Ok(0));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Finish));
+        assert_eq!(consumer.next_event(), None);
+        assert_eq!(consumer.write(&[65]), Err(ConsumerError::Terminated));
+    }
+
+    #[test]
+    fn finish_drops_queued_requests() {
+        let mut consumer = SinkConsumer::new();
+        assert_eq!(consumer.write(&[65]), Ok(1));
+        assert_eq!(consumer.write(&[]), Ok(0));
+        assert_eq!(consumer.collect_events(), vec![ConsumerEvent::Finish]);
+    }
+}
@@ -0,0 +1,248 @@
+use std::cmp;
+use std::io::Write;
+use std::collections::VecDeque;
+
+use crate::{BoxedWriteAdapterConsumer, Consumer, ConsumerError, ConsumerEvent, TerminationReason, UpdateResult, WriteAdapterConsumer};
+
+
+// Writes everything it's given to two writers. Each side keeps its own
+// buffering, and a Request is only passed on once both sides have asked for
+// more, so the slower writer sets the pace.
+pub struct TeeConsumer<'a> {
+    left: BoxedWriteAdapterConsumer<'a>,
+    right: BoxedWriteAdapterConsumer<'a>,
+    left_credit: usize,
+    right_credit: usize,
+    left_finished: bool,
+    right_finished: bool,
+    event_queue: VecDeque<ConsumerEvent>,
+    coalesce_requests: bool,
+    closed: bool,
+}
+
+impl<'a> TeeConsumer<'a> {
+    pub fn new<A: 'a + Write, B: 'a + Write>(left: A, right: B) -> TeeConsumer<'a> {
+        let mut consumer = TeeConsumer {
+            left: WriteAdapterConsumer::new(Box::new(left)),
+            right: WriteAdapterConsumer::new(Box::new(right)),
+            left_credit: 0,
+            right_credit: 0,
+            left_finished: false,
+            right_finished: false,
+            event_queue: VecDeque::new(),
+            coalesce_requests: true,
+            closed: false,
+        };
+
+        consumer.pump();
+
+        consumer
+    }
+
+    pub fn demand(&self) -> usize {
+        cmp::min(self.left.demand(), self.right.demand())
+    }
+
+    // Same as the coalesce_requests builder option on WriteAdapterConsumer,
+    // and on by default like there.
+    pub fn coalesce_requests(mut self, coalesce: bool) -> TeeConsumer<'a> {
+        self.coalesce_requests = coalesce;
+        self
+    }
+
+    pub fn terminate(&mut self, reason: TerminationReason) {
+        // Anything either side hasn't asked for more after, the upstream
+        // hasn't been given demand back for either.
+        let unacked = self.left.unacked() + self.left_credit;
+        self.terminate_with(reason, unacked);
+    }
+
+    fn terminate_with(&mut self, reason: TerminationReason, unacked: usize) {
+        if self.closed {
+            return;
+        }
+
+        self.left.terminate(reason);
+        self.right.terminate(reason);
+        self.left.drain_events();
+        self.right.drain_events();
+        self.closed = true;
+        self.emit(ConsumerEvent::Termination { reason, unacked });
+    }
+
+    // Collects what both sides have signalled and turns it into events for
+    // this consumer.
+    fn pump(&mut self) {
+        let mut termination = None;
+
+        let sides = [
+            (&mut self.left, &mut self.left_credit, &mut self.left_finished),
+            (&mut self.right, &mut self.right_credit, &mut self.right_finished),
+        ];
+
+        for (consumer, credit, finished) in sides {
+            while let Some(event) = consumer.next_event() {
+                match event {
+                    ConsumerEvent::Request(n) => {
+                        *credit += n;
+                    },
+                    ConsumerEvent::Finish => {
+                        *finished = true;
+                    },
+                    ConsumerEvent::Termination { reason, unacked } => {
+                        termination = Some((reason, unacked + *credit));
+                    },
+                    ConsumerEvent::Flush | ConsumerEvent::Drained | ConsumerEvent::Error(_) |
+                    ConsumerEvent::HighWater | ConsumerEvent::LowWater => {
+                    },
+                }
+            }
+        }
+
+        if let Some((reason, unacked)) = termination {
+            self.terminate_with(reason, unacked);
+            return;
+        }
+
+        let n = cmp::min(self.left_credit, self.right_credit);
+
+        if n > 0 {
+            self.left_credit -= n;
+            self.right_credit -= n;
+            self.emit(ConsumerEvent::Request(n));
+        }
+
+        if self.left_finished && self.right_finished && !self.closed {
+            // Same as WriteAdapterConsumer::finish(): a Request still queued
+            // is stale once the stream has finished.
+            self.closed = true;
+            self.event_queue.retain(|event| !matches!(event, ConsumerEvent::Request(_)));
+            self.emit(ConsumerEvent::Finish);
+        }
+    }
+}
+
+impl<'a> Consumer<[u8]> for TeeConsumer<'a> {
+    fn write(&mut self, data: &[u8]) -> Result<usize, ConsumerError> {
+        if self.closed {
+            return Err(ConsumerError::Terminated);
+        }
+
+        // Check both sides up front so data never ends up on just one.
+        if !data.is_empty() {
+            self.left.check_writable(data.len(), 1)?;
+            self.right.check_writable(data.len(), 1)?;
+        }
+
+        let result = self.left.write(data)
+            .and_then(|left| self.right.write(data).map(|right| cmp::min(left, right)));
+
+        self.pump();
+
+        result
+    }
+
+    fn emit(&mut self, event: ConsumerEvent) -> bool {
+        if let ConsumerEvent::Request(m) = event {
+            let coalesce = self.coalesce_requests;
+
+            if let Some(ConsumerEvent::Request(n)) = self.event_queue.back_mut().filter(|_| coalesce) {
+                *n += m;
+                return true;
+            }
+        }
+
+        self.event_queue.push_back(event);
+        true
+    }
+
+    fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.event_queue.pop_front()
+    }
+
+    fn update(&mut self) -> UpdateResult {
+        let left = self.left.update();
+        let right = self.right.update();
+        self.pump();
+
+        left.combine(right)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io;
+    use crate::test_util::BlockingWriter;
+
+    struct FailWriter {
+    }
+
+    impl Write for FailWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("YOLO"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_to_both() {
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        {
+            let mut consumer = TeeConsumer::new(&mut left, &mut right);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65, 66]), Ok(2));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[67]), Ok(1));
+        }
+        assert_eq!(left, vec![65, 66, 67]);
+        assert_eq!(right, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn waits_for_slower_side() {
+        let mut left = Vec::new();
+        let mut right = BlockingWriter{ blocks: 1, data: Vec::new() };
+        {
+            let mut consumer = TeeConsumer::new(&mut left, &mut right);
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65]), Ok(0));
+            assert_eq!(consumer.next_event(), None);
+            assert_eq!(consumer.demand(), 0);
+            assert_eq!(consumer.write(&[66]), Err(ConsumerError::WriteWithoutRequest));
+
+            consumer.update();
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.demand(), 1);
+        }
+        assert_eq!(left, vec![65]);
+        assert_eq!(right.data, vec![65]);
+    }
+
+    #[test]
+    fn failure_on_one_side_terminates() {
+        let mut left = Vec::new();
+        {
+            let mut consumer = TeeConsumer::new(&mut left, FailWriter{});
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+            assert_eq!(consumer.write(&[65]), Err(ConsumerError::Io(io::Error::other("YOLO"))));
+            assert_eq!(consumer.next_event(), Some(ConsumerEvent::Termination { reason: TerminationReason::WriteError(io::ErrorKind::Other), unacked: 0 }));
+            assert_eq!(consumer.write(&[66]), Err(ConsumerError::Terminated));
+        }
+        assert_eq!(left, vec![65]);
+    }
+
+    #[test]
+    fn finishes_when_both_finish() {
+        let mut consumer = TeeConsumer::new(Vec::new(), Vec::new());
+        assert_eq!(consumer.write(&[]), Ok(0));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Finish));
+        assert_eq!(consumer.write(&[65]), Err(ConsumerError::Terminated));
+    }
+}
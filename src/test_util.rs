@@ -0,0 +1,70 @@
+use std::cell::Cell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::Clock;
+
+
+// Writers and a clock for the tests of more than one module.
+
+// Fails with WouldBlock for the first blocks calls, and takes everything
+// after that.
+pub(crate) struct BlockingWriter {
+    pub(crate) blocks: usize,
+    pub(crate) data: Vec<u8>,
+}
+
+impl Write for BlockingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.blocks > 0 {
+            self.blocks -= 1;
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "blocked"))
+        }
+        else {
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Accepts a single byte, then blocks on the next call, and so on.
+#[derive(Default)]
+pub(crate) struct PartialWriter {
+    pub(crate) blocked: bool,
+    pub(crate) data: Vec<u8>,
+}
+
+impl Write for PartialWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.blocked {
+            self.blocked = false;
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "blocked"))
+        }
+        else {
+            self.blocked = true;
+            self.data.push(buf[0]);
+            Ok(1)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Only moves on when the test changes elapsed.
+pub(crate) struct MockClock {
+    pub(crate) start: Instant,
+    pub(crate) elapsed: Rc<Cell<Duration>>,
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+}
@@ -0,0 +1,150 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::AsyncWrite;
+
+use crate::{ConsumerError, ConsumerEvent};
+use crate::async_core::AsyncCore;
+
+
+pub struct AsyncWriteAdapterConsumer<W: AsyncWrite + Unpin> {
+    writer: W,
+    core: AsyncCore,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWriteAdapterConsumer<W> {
+    pub fn new(writer: W) -> AsyncWriteAdapterConsumer<W> {
+        AsyncWriteAdapterConsumer::with_demand(writer, 1)
+    }
+
+    pub fn with_demand(writer: W, initial_demand: usize) -> AsyncWriteAdapterConsumer<W> {
+        AsyncWriteAdapterConsumer {
+            writer,
+            core: AsyncCore::new(initial_demand),
+        }
+    }
+
+    // Same as the coalesce_requests builder option on WriteAdapterConsumer,
+    // and on by default like there.
+    pub fn coalesce_requests(mut self, coalesce: bool) -> AsyncWriteAdapterConsumer<W> {
+        self.core.set_coalesce_requests(coalesce);
+        self
+    }
+
+    pub fn demand(&self) -> usize {
+        self.core.demand()
+    }
+
+    pub fn pending_bytes(&self) -> usize {
+        self.core.pending_bytes()
+    }
+
+    pub fn next_event(&mut self) -> Option<ConsumerEvent> {
+        self.core.next_event()
+    }
+
+    // Hands data to the writer, buffering whatever it isn't ready for yet.
+    // Buffered data from an earlier call is written out first, so this is
+    // Pending for as long as the writer can't take that.
+    pub fn poll_write(&mut self, cx: &mut Context, data: &[u8]) -> Poll<Result<(), ConsumerError>> {
+        let AsyncWriteAdapterConsumer { writer, core } = self;
+        core.poll_write(&mut |buf: &[u8]| Pin::new(&mut *writer).poll_write(cx, buf), data)
+    }
+
+    // Retries any buffered data, giving the demand back once it's all out.
+    pub fn poll_drain(&mut self, cx: &mut Context) -> Poll<Result<(), ConsumerError>> {
+        let AsyncWriteAdapterConsumer { writer, core } = self;
+        core.poll_drain(&mut |buf: &[u8]| Pin::new(&mut *writer).poll_write(cx, buf))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io;
+    use std::task::Waker;
+
+    // Returns Pending on every other call, accepting everything otherwise.
+    #[derive(Default)]
+    struct PendingWriter {
+        pending: bool,
+        data: Vec<u8>,
+    }
+
+    impl AsyncWrite for PendingWriter {
+        fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.pending = !self.pending;
+
+            if self.pending {
+                Poll::Pending
+            }
+            else {
+                self.data.extend_from_slice(buf);
+                Poll::Ready(Ok(buf.len()))
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn poll_write_to_vec() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut consumer = AsyncWriteAdapterConsumer::new(Vec::new());
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.poll_write(&mut cx, &[65, 66]), Poll::Ready(Ok(())));
+        assert_eq!(consumer.poll_write(&mut cx, &[67]), Poll::Ready(Ok(())));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(2)));
+        assert_eq!(consumer.writer, vec![65, 66, 67]);
+    }
+
+    #[test]
+    fn requests_can_be_left_separate() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut consumer = AsyncWriteAdapterConsumer::new(Vec::new()).coalesce_requests(false);
+        assert_eq!(consumer.poll_write(&mut cx, &[65]), Poll::Ready(Ok(())));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.next_event(), None);
+    }
+
+    #[test]
+    fn pending_data_is_retried() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut consumer = AsyncWriteAdapterConsumer::new(PendingWriter::default());
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+
+        assert_eq!(consumer.poll_write(&mut cx, &[65]), Poll::Ready(Ok(())));
+        assert_eq!(consumer.pending_bytes(), 1);
+        assert_eq!(consumer.demand(), 0);
+        assert_eq!(consumer.next_event(), None);
+
+        assert_eq!(consumer.poll_drain(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(consumer.pending_bytes(), 0);
+        assert_eq!(consumer.next_event(), Some(ConsumerEvent::Request(1)));
+        assert_eq!(consumer.writer.data, vec![65]);
+    }
+
+    #[test]
+    fn buffer_is_drained_before_new_data() {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut consumer = AsyncWriteAdapterConsumer::with_demand(PendingWriter::default(), 2);
+
+        assert_eq!(consumer.poll_write(&mut cx, &[65]), Poll::Ready(Ok(())));
+        assert_eq!(consumer.poll_write(&mut cx, &[66]), Poll::Ready(Ok(())));
+        assert_eq!(consumer.writer.data, vec![65]);
+        assert_eq!(consumer.pending_bytes(), 1);
+
+        assert_eq!(consumer.poll_drain(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(consumer.writer.data, vec![65, 66]);
+        assert_eq!(consumer.demand(), 2);
+    }
+}
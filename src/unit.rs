@@ -0,0 +1,53 @@
+use std::ops::Deref;
+
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+
+
+// Data waiting in a consumer's buffer. Anything handed over as Bytes is kept
+// that way, so buffering it doesn't mean copying it.
+#[derive(Debug)]
+pub(crate) enum Unit {
+    Owned(Vec<u8>),
+    #[cfg(feature = "bytes")]
+    Shared(Bytes),
+}
+
+impl Unit {
+    // Drops the first n bytes, once the writer has taken them.
+    pub(crate) fn advance(&mut self, n: usize) {
+        match self {
+            Unit::Owned(data) => {
+                data.drain(..n);
+            },
+            #[cfg(feature = "bytes")]
+            Unit::Shared(data) => {
+                *data = data.slice(n..);
+            },
+        }
+    }
+}
+
+impl Deref for Unit {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Unit::Owned(data) => data,
+            #[cfg(feature = "bytes")]
+            Unit::Shared(data) => data,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Unit {
+    fn from(data: Vec<u8>) -> Unit {
+        Unit::Owned(data)
+    }
+}
+
+impl PartialEq<Vec<u8>> for Unit {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        **self == other[..]
+    }
+}